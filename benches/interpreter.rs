@@ -0,0 +1,174 @@
+//! Times the interpreter on a few workloads.
+//!
+//! Run with `cargo bench`. Each workload is run several times and the
+//! fastest run is reported so that noise from the rest of the system does not
+//! hide changes to the interpreter.
+//!
+//! The times are saved to `RESULTS` and each run is compared with the one
+//! before it, so running the bench before and after a change shows its
+//! effect. Each time is also compared with the token-stream interpreter that
+//! was replaced by the bytecode compiler, whose times are in `BASELINE`.
+
+use lambda_core::{Interpreter, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 10;
+
+/// The file in the target directory where the times of the last run are
+/// kept.
+const RESULTS: &str = "lcore-bench.txt";
+
+/// The times of the token-stream interpreter, by workload.
+const BASELINE: &str = include_str!("token-stream.txt");
+
+/// The recursive definition, which makes a call for every step.
+const RECURSIVE_FIB: &str = "
+(defn 'rfib ['n] '[
+    (if (< n 2)
+        '[n]
+        '[(+ (rfib (- n 1)) (rfib (- n 2)))])
+])
+";
+
+/// Blocks that are not written out in the call that runs them are run by the
+/// builtins rather than compiled in place.
+const BUILTIN_BLOCKS: &str = "
+(set 'body '[(set 'total (+ total i))])
+
+(defn 'sum-to ['n] '[
+    (set 'total 0)
+    (loop 'i n body)
+    (ret total)
+])
+
+(defn 'safe-div ['a 'b] '[
+    (try '[(ret (/ a b))] 'e '[(ret 0)])
+])
+";
+
+/// An interpreter whose output is thrown away.
+fn interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Rc::new(RefCell::new(io::sink())));
+    interpreter
+}
+
+/// Call the loop-based `fib` from `examples/fib.lcore` over and over.
+fn fib() {
+    let mut interpreter = interpreter();
+    interpreter.eval_file("examples/fib.lcore").unwrap();
+
+    for _ in 0..2_000 {
+        let result = interpreter.call_function("fib", vec![Value::Int(40)]);
+        assert_eq!(result.unwrap(), Value::Int(63_245_986));
+    }
+}
+
+fn recursive_fib() {
+    let mut interpreter = interpreter();
+    interpreter.eval_str(RECURSIVE_FIB).unwrap();
+
+    let result = interpreter.call_function("rfib", vec![Value::Int(22)]);
+    assert_eq!(result.unwrap(), Value::Int(17_711));
+}
+
+fn builtin_blocks() {
+    let mut interpreter = interpreter();
+    interpreter.eval_str(BUILTIN_BLOCKS).unwrap();
+
+    for i in 0..20_000 {
+        let result = interpreter.call_function("sum-to", vec![Value::Int(5)]);
+        assert_eq!(result.unwrap(), Value::Int(10));
+
+        let args = vec![Value::Int(i), Value::Int(i % 3)];
+        interpreter.call_function("safe-div", args).unwrap();
+    }
+}
+
+/// Parse and run a file of 100,000 top-level calls.
+fn stress() {
+    let mut interpreter = interpreter();
+    interpreter.eval_file("examples/stress.lcore").unwrap();
+}
+
+/// Run `workload` a few times.
+///
+/// Returns: The fastest run in milliseconds.
+fn bench(workload: fn()) -> f64 {
+    let mut best = Duration::MAX;
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        workload();
+        best = best.min(start.elapsed());
+    }
+
+    best.as_secs_f64() * 1000.0
+}
+
+/// Where to save the times, next to the `release` directory the bench was
+/// built in so that it works wherever the target directory is.
+fn results_path() -> PathBuf {
+    let bench = std::env::current_exe().unwrap();
+    bench.ancestors().nth(3).unwrap().join(RESULTS)
+}
+
+/// Read times saved one workload per line, skipping `#` comments.
+///
+/// Returns: The times in milliseconds by workload.
+fn parse_results(results: &str) -> HashMap<String, f64> {
+    results
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, ms) = line.split_once(' ')?;
+            Some((name.to_string(), ms.parse().ok()?))
+        })
+        .collect()
+}
+
+fn main() {
+    let workloads: [(&str, fn()); 4] = [
+        ("fib", fib),
+        ("recursive-fib", recursive_fib),
+        ("builtin-blocks", builtin_blocks),
+        ("stress", stress),
+    ];
+
+    let path = results_path();
+    let previous =
+        parse_results(&fs::read_to_string(&path).unwrap_or_default());
+    let baseline = parse_results(BASELINE);
+    let mut results = String::new();
+
+    println!(
+        "{:<16} {:>10} {:>10} {:>8} {:>14}",
+        "", "ms", "last ms", "change", "token-stream"
+    );
+    for (name, workload) in workloads.iter() {
+        let ms = bench(*workload);
+        results.push_str(&format!("{} {}\n", name, ms));
+
+        let change = match previous.get(*name) {
+            Some(last) => {
+                format!("{:>10.2} {:>+7.1}%", last, (ms - last) / last * 100.0)
+            }
+            None => format!("{:>10} {:>8}", "", ""),
+        };
+        println!(
+            "{:<16} {:>10.2} {} {:>13.1}x",
+            name,
+            ms,
+            change,
+            baseline[*name] / ms
+        );
+    }
+
+    fs::write(&path, results).expect("Cannot save the bench results");
+}
@@ -0,0 +1,104 @@
+//! Derive macros for `lambda_core::convert::{FromValue, IntoValue}`.
+//!
+//! Only structs with named fields can be derived. Each field is stored under
+//! its own name, and its type must implement the trait being derived.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+
+/// Convert a struct into a LambdaCore dict keyed by its field names.
+#[proc_macro_derive(IntoValue)]
+pub fn derive_into_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match named_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let name = &input.ident;
+    let keys = fields.iter().map(|field| field.to_string());
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+
+    let expanded: TokenStream2 = quote! {
+        impl #impl_generics ::lambda_core::convert::IntoValue
+            for #name #ty_generics #where_clause
+        {
+            fn into_value(self) -> ::lambda_core::Value {
+                ::lambda_core::convert::lcore_dict_from_fields(vec![
+                    #((
+                        #keys,
+                        ::lambda_core::convert::IntoValue::into_value(
+                            self.#fields
+                        ),
+                    ),)*
+                ])
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+/// Read a struct from a LambdaCore dict or struct with the same fields.
+#[proc_macro_derive(FromValue)]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match named_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let name = &input.ident;
+    let type_name = name.to_string();
+    let keys = fields.iter().map(|field| field.to_string());
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+
+    let expanded: TokenStream2 = quote! {
+        impl #impl_generics ::lambda_core::convert::FromValue
+            for #name #ty_generics #where_clause
+        {
+            fn from_value(
+                value: ::lambda_core::Value,
+            ) -> ::std::result::Result<Self, ::lambda_core::LCoreError> {
+                let mut fields =
+                    ::lambda_core::convert::Fields::new(value, #type_name)?;
+
+                Ok(#name {
+                    #(#fields: fields.take(#keys)?,)*
+                })
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+/// The names of the fields of a struct, or an error pointing at anything
+/// else.
+fn named_fields(input: &DeriveInput) -> Result<Vec<Ident>, syn::Error> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields
+                .named
+                .iter()
+                .map(|field| field.ident.clone().unwrap())
+                .collect()),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "Only structs with named fields can be converted",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "Only structs with named fields can be converted",
+        )),
+    }
+}
@@ -0,0 +1,146 @@
+//! The tree of forms that the parser produces and the compiler consumes.
+//!
+//! Quoted code stays in this form at run time, so a macro receives and
+//! returns trees rather than text.
+
+use crate::lcore::*;
+use crate::symbol::*;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+/// A form of parsed code, along with where it was written. Code made while
+/// running, such as by a macro, is placed at the form that made it.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A call such as `(f x)`: the function followed by its arguments.
+    Call(Span, Rc<[Expr]>),
+
+    /// A value written out directly, such as `5` or `"text"`. Code generated
+    /// by a macro can hold any other value this way too.
+    Literal(Span, Value),
+
+    /// A name such as `x`, interned when it is parsed.
+    Symbol(Span, Symbol),
+
+    /// Code that is not run, such as `'x` or `'[...]`.
+    Quote(Span, Rc<Expr>),
+
+    /// An array literal such as `[x 1]`.
+    Array(Span, Vec<Expr>),
+
+    /// A template such as `` `[(print ,x)] `` that is filled in when run.
+    QuasiQuote(Span, Rc<Expr>),
+
+    /// A form such as `,x` in a template, replaced by its value.
+    Unquote(Span, Box<Expr>),
+
+    /// A form such as `,@x` in a template, replaced by the items of its
+    /// value.
+    Splice(Span, Box<Expr>),
+}
+
+impl Expr {
+    /// Where the form was written.
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Call(span, _)
+            | Expr::Literal(span, _)
+            | Expr::Symbol(span, _)
+            | Expr::Quote(span, _)
+            | Expr::Array(span, _)
+            | Expr::QuasiQuote(span, _)
+            | Expr::Unquote(span, _)
+            | Expr::Splice(span, _) => span,
+        }
+    }
+}
+
+impl PartialEq for Expr {
+    /// Forms are equal when they are written the same way, wherever they
+    /// were written.
+    fn eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::Call(_, a), Expr::Call(_, b)) => a == b,
+            (Expr::Literal(_, a), Expr::Literal(_, b)) => a == b,
+            (Expr::Symbol(_, a), Expr::Symbol(_, b)) => a == b,
+            (Expr::Quote(_, a), Expr::Quote(_, b)) => a == b,
+            (Expr::Array(_, a), Expr::Array(_, b)) => a == b,
+            (Expr::QuasiQuote(_, a), Expr::QuasiQuote(_, b)) => a == b,
+            (Expr::Unquote(_, a), Expr::Unquote(_, b)) => a == b,
+            (Expr::Splice(_, a), Expr::Splice(_, b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Hash for Expr {
+    /// Like equality, hashing leaves out where a form was written.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Expr::Call(_, items) => items.hash(state),
+            Expr::Literal(_, value) => value.hash(state),
+            Expr::Symbol(_, name) => name.hash(state),
+            Expr::Quote(_, quoted) | Expr::QuasiQuote(_, quoted) => {
+                quoted.hash(state)
+            }
+            Expr::Array(_, items) => items.hash(state),
+            Expr::Unquote(_, form) | Expr::Splice(_, form) => form.hash(state),
+        }
+    }
+}
+
+/// Check if a form is code that only means something when run, such as a
+/// call, rather than a name, a value or a quote.
+pub fn lcore_is_code(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Call(..)
+            | Expr::QuasiQuote(..)
+            | Expr::Unquote(..)
+            | Expr::Splice(..)
+    )
+}
+
+/// The value a form stands for when it is handled as data rather than run,
+/// such as an argument to a macro. Names become identifiers and a call is
+/// quoted as-is, so it can be run later or put back into code unchanged.
+pub fn lcore_expr_value(expr: &Expr) -> Value {
+    match expr {
+        Expr::Literal(_, value) => value.clone(),
+        Expr::Symbol(_, name) => Value::Identifier(name.name().to_string()),
+        Expr::Quote(_, quoted) => Value::Quote(quoted.clone()),
+        Expr::Array(_, items) => {
+            Value::Array(Rc::new(items.iter().map(lcore_expr_value).collect()))
+        }
+        Expr::Call(..)
+        | Expr::QuasiQuote(..)
+        | Expr::Unquote(..)
+        | Expr::Splice(..) => Value::Quote(Rc::new(expr.clone())),
+    }
+}
+
+/// The form that stands for a value when it is put back into code, such as
+/// by an unquote. This undoes `lcore_expr_value`, so a quoted call becomes
+/// the call itself. New forms are placed at `span`, the code that made the
+/// value into code.
+pub fn lcore_value_expr(value: Value, span: &Span) -> Expr {
+    match value {
+        Value::Identifier(name) => {
+            Expr::Symbol(span.clone(), Symbol::intern(&name))
+        }
+        Value::Quote(quoted) if lcore_is_code(&quoted) => {
+            Rc::unwrap_or_clone(quoted)
+        }
+        Value::Quote(quoted) => Expr::Quote(span.clone(), quoted),
+        Value::Array(items) => Expr::Array(
+            span.clone(),
+            Rc::unwrap_or_clone(items)
+                .into_iter()
+                .map(|item| lcore_value_expr(item, span))
+                .collect(),
+        ),
+        value => Expr::Literal(span.clone(), value),
+    }
+}
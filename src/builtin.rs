@@ -1,136 +1,345 @@
-use std::collections::{HashMap, VecDeque};
-
+use crate::ast::*;
+use crate::compiler::*;
 use crate::lcore::*;
-use std::io::{self, Write};
-use std::iter::FromIterator;
-use std::process::exit;
+use crate::symbol::*;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// Check that a builtin was called with between `min` and `max` arguments.
+pub fn lcore_check_arity(
+    args: &[Value],
+    func_name: &str,
+    min: usize,
+    max: usize,
+) -> Result<(), LCoreError> {
+    if args.len() >= min && args.len() <= max {
+        return Ok(());
+    }
+
+    let expected = if min == max {
+        format!("{}", min)
+    } else if max == usize::MAX {
+        format!("{}+", min)
+    } else {
+        format!("{}-{}", min, max)
+    };
+
+    Err(LCoreError::ArityError(format!(
+        "Wrong number of arguments on call to \"{}\": {}/{}",
+        func_name,
+        args.len(),
+        expected
+    )))
+}
+
+fn lcore_type_error(
+    value: &Value,
+    expected: &str,
+    func_name: &str,
+) -> LCoreError {
+    LCoreError::TypeError(format!(
+        "\"{}\" expects {} but got {:?}",
+        func_name, expected, value
+    ))
+}
+
+pub fn lcore_expect_bool(
+    value: &Value,
+    func_name: &str,
+) -> Result<bool, LCoreError> {
+    match value {
+        Value::Boolean(b) => Ok(*b),
+        _ => Err(lcore_type_error(value, "a Boolean", func_name)),
+    }
+}
+
+pub fn lcore_expect_int(
+    value: &Value,
+    func_name: &str,
+) -> Result<i64, LCoreError> {
+    match value {
+        Value::Int(i) => Ok(*i),
+        _ => Err(lcore_type_error(value, "an Int", func_name)),
+    }
+}
+
+pub fn lcore_expect_array<'a>(
+    value: &'a Value,
+    func_name: &str,
+) -> Result<&'a Vec<Value>, LCoreError> {
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => Err(lcore_type_error(value, "an Array", func_name)),
+    }
+}
+
+/// Get the code inside of a quoted block such as `'[...]` or `'(...)`.
+pub fn lcore_expect_block<'a>(
+    value: &'a Value,
+    func_name: &str,
+) -> Result<&'a Rc<Expr>, LCoreError> {
+    match value {
+        Value::Quote(q) => Ok(q),
+        _ => Err(lcore_type_error(value, "a quoted block", func_name)),
+    }
+}
+
+/// Get a name given either as a quoted identifier or as an identifier.
+pub fn lcore_expect_name(
+    value: &Value,
+    func_name: &str,
+) -> Result<Symbol, LCoreError> {
+    match value {
+        Value::Identifier(name) => Ok(Symbol::intern(name)),
+        Value::Quote(q) => match &**q {
+            &Expr::Symbol(_, name) => Ok(name),
+            _ => Err(lcore_type_error(value, "a quoted name", func_name)),
+        },
+        _ => Err(lcore_type_error(value, "a quoted name", func_name)),
+    }
+}
+
+/// Wrap the result of integer arithmetic, which is `None` on overflow.
+fn lcore_int_result(
+    result: Option<i64>,
+    func_name: &str,
+) -> Result<Value, LCoreError> {
+    result.map(Value::Int).ok_or_else(|| {
+        LCoreError::ArgumentError(format!(
+            "Integer overflow on call to \"{}\"",
+            func_name
+        ))
+    })
+}
 
-pub fn lcore_print_value(args: &mut Value) -> Result<Value, LCoreError> {
-    fn print_string(v: &String, repr: bool) {
+/// Write the text `print` would show for a value to `out`.
+pub fn lcore_format_value(out: &mut String, value: &Value, repr: bool) {
+    fn print_string(out: &mut String, v: &str, repr: bool) {
         if repr {
-            // print!("{}", v);
-            // print!("\"{}\"", v);
-            print!("\"");
-            io::stdout().write(v.as_bytes()).ok();
-            print!("\"");
+            out.push('"');
+            out.push_str(v);
+            out.push('"');
         } else {
-            // print!("{}", &v[1 .. v.len() - 1]);
-            // print!("{}", v);
-            io::stdout().write(v.as_bytes()).ok();
+            // out.push_str(&v[1 .. v.len() - 1]);
+            out.push_str(v);
         }
     }
 
-    fn print_boolean(v: &bool, repr: bool) {
-        print!("{}", if *v { "True" } else { "False" });
+    fn print_boolean(out: &mut String, v: &bool) {
+        out.push_str(if *v { "True" } else { "False" });
     }
 
-    fn print_int(v: &i64, repr: bool) {
-        print!("{}", v);
+    fn print_int(out: &mut String, v: &i64) {
+        out.push_str(&v.to_string());
     }
 
-    fn print_float(v: &f64, repr: bool) {
-        print!("{}", v);
+    fn print_float(out: &mut String, v: &f64) {
+        out.push_str(&v.to_string());
     }
 
-    fn print_null() {
-        print!("Null");
+    fn print_null(out: &mut String) {
+        out.push_str("Null");
     }
 
-    fn print_array(v: &Vec<Value>, repr: bool) {
+    fn print_array(out: &mut String, v: &Vec<Value>) {
         let length = v.len();
         let mut count = 0;
-        print!("[");
+        out.push('[');
         for value in v {
-            print_value(value, true);
+            print_value(out, value, true);
 
             count += 1;
             if count < length {
-                // print!(", ");
-                print!(" ");
+                // out.push_str(", ");
+                out.push(' ');
             }
         }
-        print!("]");
+        out.push(']');
     }
 
-    fn print_func(
-        v: &fn(&mut Value, &mut Environment) -> Result<Value, LCoreError>,
-        repr: bool,
+    fn print_func(out: &mut String, v: &NativeFunc) {
+        out.push_str(&format!("<Func {}>", v.name()));
+    }
+
+    fn print_lambda(out: &mut String, v: &Lambda) {
+        match v.name {
+            Some(ref name) => out.push_str(&format!("<Lambda {}>", name)),
+            None => out.push_str("<Lambda>"),
+        }
+    }
+
+    fn print_macro(out: &mut String, v: &Lambda) {
+        match v.name {
+            Some(ref name) => out.push_str(&format!("<Macro {}>", name)),
+            None => out.push_str("<Macro>"),
+        }
+    }
+
+    fn print_type(out: &mut String, v: &StructType) {
+        out.push_str(&format!("<Type {}>", v.name));
+    }
+
+    fn print_struct(
+        out: &mut String,
+        kind: &StructType,
+        fields: &Vec<Value>,
     ) {
-        print!("<Func at {:p}>", v);
+        out.push_str(&format!("{} {{ ", kind.name));
+        let length = fields.len();
+        let mut count = 0;
+
+        for (name, value) in kind.fields.iter().zip(fields) {
+            out.push_str(&format!("{}: ", name));
+            print_value(out, value, true);
+
+            count += 1;
+            if count < length {
+                out.push_str(", ");
+            }
+        }
+
+        out.push_str(" }");
     }
 
-    fn print_quote(v: &Box<Value>, repr: bool) {
+    fn print_quote(out: &mut String, v: &Expr) {
         // TODO(pebaz): Choose which one is better:
 
         // 1.
-        print!("(quote ");
-        print_value(v, repr);
-        print!(")");
+        out.push_str("(quote ");
+        print_expr(out, v);
+        out.push(')');
 
         // 2.
-        // print!("'");
-        // print_value(v, repr);
+        // out.push('\'');
+        // print_expr(out, v);
     }
 
-    fn print_dict(v: &HashMap<Value, Value>, repr: bool) {
-        print!("{{ ");
-        // print!("{:?}", v);
+    fn print_expr(out: &mut String, expr: &Expr) {
+        match expr {
+            Expr::Call(_, items) => {
+                out.push('(');
+                for item in items.iter() {
+                    out.push(' ');
+                    print_expr(out, item);
+                }
+                out.push_str(" )");
+            }
+            Expr::Array(_, items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    print_expr(out, item);
+                }
+                out.push(']');
+            }
+            Expr::Literal(_, v) => print_value(out, v, true),
+            Expr::Symbol(_, name) => out.push_str(&name.name()),
+            Expr::Quote(_, v) => print_quote(out, v),
+            Expr::QuasiQuote(_, v) => {
+                out.push('`');
+                print_expr(out, v);
+            }
+            Expr::Unquote(_, v) => {
+                out.push(',');
+                print_expr(out, v);
+            }
+            Expr::Splice(_, v) => {
+                out.push_str(",@");
+                print_expr(out, v);
+            }
+        }
+    }
+
+    fn print_dict(out: &mut String, v: &Dict) {
+        out.push_str("{ ");
         let length = v.len();
         let mut count = 0;
 
         for (key, value) in v {
-            print_value(key, true);
-            print!(": ");
-            print_value(value, true);
+            print_value(out, key, true);
+            out.push_str(": ");
+            print_value(out, value, true);
 
             count += 1;
             if count < length {
-                print!(", ");
-                // print!(" ");
+                out.push_str(", ");
+                // out.push(' ');
             }
         }
 
-        print!(" }}");
+        out.push_str(" }");
     }
 
-    fn print_value(value: &Value, repr: bool) {
+    fn print_value(out: &mut String, value: &Value, repr: bool) {
         match value {
             // Print, stripping out first and last double quotes `"`
-            Value::String(v) => print_string(v, repr),
-            Value::Boolean(v) => print_boolean(v, repr),
-            Value::Int(v) => print_int(v, repr),
-            Value::Float(v) => print_float(v, repr),
-            Value::Array(v) => print_array(v, repr),
-            Value::Func { f: v } => print_func(v, repr),
-            Value::Null => print_null(),
+            Value::String(v) => print_string(out, v, repr),
+            Value::Boolean(v) => print_boolean(out, v),
+            Value::Int(v) => print_int(out, v),
+            Value::Float(v) => print_float(out, v),
+            Value::Array(v) => print_array(out, v),
+            Value::Func(v) => print_func(out, v),
+            Value::Lambda(v) => print_lambda(out, v),
+            Value::Macro(v) => print_macro(out, v),
+            Value::Type(v) => print_type(out, v),
+            Value::Struct { kind, fields } => {
+                print_struct(out, kind, fields)
+            }
+            Value::Null => print_null(out),
             Value::Identifier(v) => {
                 // TODO
                 // Will only get here if value was quoted
                 // CHECK ON THIS LATER, not sure any more
-                // print!("'{}", v);
-                print!("{}", v);
+                // out.push('\'');
+                out.push_str(v);
             }
-            Value::Quote(v) => print_quote(v, true),
-            Value::Dict(v) => print_dict(v, repr),
-            Value::OpenFunc => print!("("),
-            Value::CloseFunc => print!(")"),
-            _ => {}
+            Value::Quote(v) => print_quote(out, v),
+            Value::Dict(v) => print_dict(out, v),
         }
     }
 
+    print_value(out, value, repr);
+}
+
+/// Write text to the output stream of the environment.
+pub fn lcore_write_output(
+    text: &str,
+    symbol_table: &mut Environment,
+) -> Result<(), LCoreError> {
+    symbol_table
+        .output
+        .borrow_mut()
+        .write_all(text.as_bytes())
+        .map_err(|err| {
+            LCoreError::IOError(format!("Cannot write output: {}", err))
+        })
+}
+
+/// Print at most one value, followed by `end`.
+pub fn lcore_print_value(
+    args: &mut Value,
+    end: &str,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
     let args = args.as_array();
 
     if args.len() > 1 {
         // crash(format!("Can only print 1 value at a time right now."));
-        return Err(LCoreError::ArgumentError(format!(
-            "Can only print 1 value at a time right now."
-        )));
+        return Err(LCoreError::ArgumentError(
+            "Can only print 1 value at a time right now.".to_string(),
+        ));
     }
 
-    let value = args.iter().next().unwrap();
-
-    print_value(value, false);
+    let mut text = String::new();
+    if let Some(value) = args.first() {
+        lcore_format_value(&mut text, value, false);
+    }
+    text.push_str(end);
 
+    lcore_write_output(&text, symbol_table)?;
     Ok(Value::Null)
 }
 
@@ -138,105 +347,371 @@ pub fn lcore_prin(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    lcore_print_value(args).ok();
-    Ok(Value::Null)
+    lcore_print_value(args, "", symbol_table)
 }
 
 pub fn lcore_print(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    lcore_print_value(args).ok();
-    println!("");
-    Ok(Value::Null)
+    lcore_print_value(args, "\n", symbol_table)
 }
 
-pub fn lcore_quit(
+/// Read a line from the input stream without its line ending, showing an
+/// optional prompt first. Returns `Null` once there is nothing left to read.
+pub fn lcore_input(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    exit(0);
+    let args = args.as_array();
+    lcore_check_arity(args, "input", 0, 1)?;
+
+    if let Some(prompt) = args.first() {
+        let mut text = String::new();
+        lcore_format_value(&mut text, prompt, false);
+        lcore_write_output(&text, symbol_table)?;
+    }
+
+    // NOTE(pebaz): The prompt has no newline so it may still be buffered
+    symbol_table.output.borrow_mut().flush().ok();
+
+    let mut line = String::new();
+    let read = symbol_table
+        .input
+        .borrow_mut()
+        .read_line(&mut line)
+        .map_err(|err| {
+            LCoreError::IOError(format!("Cannot read input: {}", err))
+        })?;
+
+    if read == 0 {
+        return Ok(Value::Null);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    Ok(Value::String(Rc::new(line)))
+}
+
+/// Stop the program, exiting with the given status or 0.
+pub fn lcore_quit(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "exit", 0, 1)?;
+
+    let status = match args.first() {
+        Some(value) => lcore_expect_int(value, "exit")?,
+        None => 0,
+    };
+
+    match i32::try_from(status) {
+        Ok(status) => Err(LCoreError::ExitError(status)),
+        Err(_) => Err(LCoreError::ArgumentError(format!(
+            "Exit status out of range on call to \"exit\": {}",
+            status
+        ))),
+    }
 }
 
 pub fn lcore_set(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
+    let args = args.as_array();
+    lcore_check_arity(args, "set", 2, 2)?;
+
+    let var = lcore_expect_name(&args[0], "set")?;
+    symbol_table.insert(var, args[1].clone());
+
+    Ok(Value::Null)
+}
+
+pub fn lcore_loop(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "loop", 3, 3)?;
 
-    let var = args
-        .next()
-        .expect("Not enough arguments on call to \"set\": 0/2");
+    let var = lcore_expect_name(&args[0], "loop")?;
+    let iters = lcore_expect_int(&args[1], "loop")?;
+    let body = lcore_expect_block(&args[2], "loop")?;
 
-    let value = args
-        .next()
-        .expect("Not enough arguments on call to \"set\": 1/2");
+    symbol_table.push();
+
+    for i in 0..iters {
+        symbol_table.insert(var, Value::Int(i));
 
-    match var {
-        // Identifier
-        Value::Identifier(v) => {
-            symbol_table.insert(v.clone().to_string(), value.clone());
+        if let Err(err) = lcore_interpret_block(body, symbol_table, false) {
+            match err {
+                LCoreError::BreakError => break,
+
+                // NOTE(pebaz): Leave the loop's scope before unwinding so that
+                // an error caught by `try` does not leave it behind.
+                _ => {
+                    symbol_table.pop();
+                    return Err(err);
+                }
+            }
         }
+    }
+
+    symbol_table.pop();
+    Ok(Value::Null)
+}
 
-        // Quoted Identifier
-        Value::Quote(v) => {
-            let mystr = v.as_identifier();
-            symbol_table.insert(mystr.clone().to_string(), value.clone());
+/// Turn an array of quoted identifiers into the parameter names of a
+/// function.
+fn lcore_lambda_params(
+    arguments: &Value,
+    func_name: &str,
+) -> Result<Vec<Symbol>, LCoreError> {
+    let arguments = match arguments {
+        Value::Array(v) => v,
+        _ => {
+            return Err(LCoreError::ArgumentError(format!(
+                "\"{}\" expects an array of argument names",
+                func_name
+            )))
         }
+    };
 
-        _ => (),
+    let mut params = Vec::with_capacity(arguments.len());
+    for argument in arguments.iter() {
+        match argument {
+            Value::Quote(q) => match &**q {
+                &Expr::Symbol(_, name) => params.push(name),
+                _ => {
+                    return Err(LCoreError::ArgumentError(format!(
+                        "Invalid argument name on call to \"{}\"",
+                        func_name
+                    )))
+                }
+            },
+            _ => {
+                return Err(LCoreError::ArgumentError(format!(
+                    "Argument names passed to \"{}\" must be quoted",
+                    func_name
+                )))
+            }
+        }
     }
 
-    Ok(Value::Null)
+    Ok(params)
 }
 
-pub fn lcore_loop(
+/// Build a function value out of its argument names and a quoted body.
+fn lcore_make_lambda(
+    name: Option<String>,
+    arguments: &Value,
+    body: &Value,
+    func_name: &str,
+    symbol_table: &Environment,
+) -> Result<Value, LCoreError> {
+    let params = lcore_lambda_params(arguments, func_name)?;
+
+    let body = match body {
+        Value::Quote(q) => match &**q {
+            Expr::Array(_, body) => body,
+            _ => return Err(LCoreError::ArgumentError(format!(
+                "Body passed to \"{}\" must be a quoted array",
+                func_name
+            ))),
+        },
+        _ => {
+            return Err(LCoreError::ArgumentError(format!(
+                "Body passed to \"{}\" must be a quoted array",
+                func_name
+            )))
+        }
+    };
+
+    Ok(Value::Lambda(Rc::new(Lambda {
+        name: name.clone(),
+        code: Rc::new(lcore_compile_function(name, params, body)),
+        env: symbol_table.capture(),
+    })))
+}
+
+/// Create an anonymous function. The resulting value can be stored, passed
+/// to other functions and called like any function defined with `defn`.
+pub fn lcore_fn(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    symbol_table.push();
-    let mut args = args.as_array().iter();
-
-    let quote = args
-        .next()
-        .expect("Not enough arguments on call to \"loop\": 0/3");
-    let iters = args
-        .next()
-        .expect("Not enough arguments on call to \"loop\": 1/3");
-    let body = args
-        .next()
-        .expect("Not enough arguments on call to \"loop\": 2/3");
-
-    for i in 0..*iters.as_int() {
-        let mut loop_body = match body.as_value().clone() {
-            Value::Array(v) => VecDeque::from_iter(v),
-            _ => unreachable!(),
-        };
+    // Array<Quoted(Identifier)>
+    // Quoted(Array<Value>) (The code to run later)
+
+    let args = args.as_array();
+
+    lcore_check_arity(args, "fn", 2, 2)?;
+
+    lcore_make_lambda(None, &args[0], &args[1], "fn", symbol_table)
+}
+
+/// Define a struct type with the given field names and bind it to a name in
+/// the current scope.
+pub fn lcore_type(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    lcore_check_arity(args, "type", 2, 2)?;
 
-        if let Value::Identifier(s) = quote.as_value() {
-            symbol_table.insert(s.clone().to_string(), Value::Int(i));
+    let name = lcore_expect_name(&args[0], "type")?.to_string();
+
+    let tokens = match &args[1] {
+        Value::Quote(q) => match &**q {
+            Expr::Array(_, v) => v,
+            _ => {
+                return Err(LCoreError::ArgumentError(format!(
+                    "Fields of \"{}\" must be a quoted list",
+                    name
+                )))
+            }
+        },
+        _ => {
+            return Err(LCoreError::ArgumentError(format!(
+                "Fields of \"{}\" must be a quoted list",
+                name
+            )))
         }
+    };
 
-        if let Err(err) = lcore_interpret(&mut loop_body, symbol_table) {
-            match err {
-                LCoreError::LambdaCoreError(..) => return Err(err),
-                LCoreError::IndexError(..) => return Err(err),
-                LCoreError::ArgumentError(..) => return Err(err),
-                LCoreError::NameError(..) => return Err(err),
-                // LCoreError::ReturnError(v) => println!("CANNOT RETURN FROM
-                // FOR LOOP"),
-                LCoreError::ReturnError => return Err(err),
-                LCoreError::BreakError => break,
+    let mut fields = Vec::new();
+    for token in tokens {
+        match token {
+            Expr::Symbol(_, field) => {
+                let field = field.name();
+                if fields.iter().any(|f| f == &*field) {
+                    return Err(LCoreError::ArgumentError(format!(
+                        "Duplicate field \"{}\" in \"{}\"",
+                        field, name
+                    )));
+                }
+                fields.push(field.to_string());
+            }
+            _ => {
+                return Err(LCoreError::ArgumentError(format!(
+                    "Fields of \"{}\" must be identifiers",
+                    name
+                )))
             }
         }
     }
 
-    symbol_table.pop();
+    let kind = StructType {
+        name: name.clone(),
+        fields,
+    };
+    symbol_table.insert(name, Value::Type(Rc::new(kind)));
+
     Ok(Value::Null)
 }
 
-/// Stuff the code to run in a list value in the symbol table. Make sure to
-/// store the variables to bind at call time.
+/// Create an instance of a struct type, one argument per field.
+pub fn lcore_new(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    lcore_check_arity(args, "new", 1, usize::MAX)?;
+
+    let kind = match &args[0] {
+        Value::Type(kind) => kind,
+        other => {
+            return Err(LCoreError::TypeError(format!(
+                "Cannot create an instance of {:?}",
+                other
+            )))
+        }
+    };
+
+    let fields = &args[1..];
+    if fields.len() != kind.fields.len() {
+        return Err(LCoreError::ArityError(format!(
+            "\"{}\" takes {} fields but {} were given",
+            kind.name,
+            kind.fields.len(),
+            fields.len()
+        )));
+    }
+
+    Ok(Value::Struct {
+        kind: kind.clone(),
+        fields: fields.to_vec(),
+    })
+}
+
+/// Bind a macro to a name in the current scope. A macro is called with its
+/// arguments unevaluated and returns the code to run in its place.
+pub fn lcore_defmacro(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    lcore_check_arity(args, "defmacro", 3, 3)?;
+
+    let name = lcore_expect_name(&args[0], "defmacro")?.to_string();
+
+    let mac = match lcore_make_lambda(
+        Some(name.clone()),
+        &args[1],
+        &args[2],
+        "defmacro",
+        symbol_table,
+    )? {
+        Value::Lambda(l) => Value::Macro(l),
+        _ => unreachable!(),
+    };
+    symbol_table.insert(name, mac);
+
+    Ok(Value::Null)
+}
+
+/// Expand the macro call in a quoted block of code once without running it.
+/// Code that does not call a macro is returned unchanged.
+pub fn lcore_macroexpand(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    lcore_check_arity(args, "macroexpand", 1, 1)?;
+
+    let code = &args[0];
+    let forms = match code {
+        Value::Quote(q) => match &**q {
+            Expr::Array(_, forms) => forms,
+            _ => return Ok(code.clone()),
+        },
+        _ => return Ok(code.clone()),
+    };
+
+    if let [Expr::Call(_, items)] = forms.as_slice() {
+        if let Some(&Expr::Symbol(_, name)) = items.first() {
+            if let Some(Value::Macro(mac)) = symbol_table.get(name) {
+                let macro_args = lcore_macro_arguments(&items[1..]);
+                return lcore_expand_macro(mac, macro_args, symbol_table);
+            }
+        }
+    }
+
+    Ok(code.clone())
+}
+
+/// Bind a function to a name in the current scope. Make sure to store the
+/// variables to bind at call time.
 pub fn lcore_defn(
     args: &mut Value,
     symbol_table: &mut Environment,
@@ -245,339 +720,1118 @@ pub fn lcore_defn(
     // Array<Quoted(Identifier)>
     // Quoted(Array<Value>) (The code to run later)
 
-    let mut args = args.as_array().iter();
+    let args = args.as_array();
+    lcore_check_arity(args, "defn", 3, 3)?;
+
+    let name = lcore_expect_name(&args[0], "defn")?.to_string();
+
+    let def = lcore_make_lambda(
+        Some(name.clone()),
+        &args[1],
+        &args[2],
+        "defn",
+        symbol_table,
+    )?;
+    symbol_table.insert(name, def);
+
+    Ok(Value::Null)
+}
+
+pub fn lcore_get(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "get", 2, 3)?;
+
+    let key = match &args[1] {
+        Value::Quote(q) => lcore_expr_value(q),
+        key => key.clone(),
+    };
 
-    let name = args
-        .next()
-        .expect("Not enough arguments on call to \"defn\": 0/3");
-    let arguments = args
-        .next()
-        .expect("Not enough arguments on call to \"defn\": 1/3");
-    let body = args
-        .next()
-        .expect("Not enough arguments on call to \"defn\": 2/3");
+    // NOTE(pebaz): A default only stands in for a missing key or index, so
+    // indexing something that cannot be indexed is still an error.
+    match (lcore_get_item(&args[0], &key), args.get(2)) {
+        (Err(LCoreError::KeyError(_)), Some(default))
+        | (Err(LCoreError::IndexError(_)), Some(default)) => {
+            Ok(default.clone())
+        }
+        (result, _) => result,
+    }
+}
 
-    let def = Value::Array(vec![arguments.clone(), body.as_value().clone()]);
+/// The element of a string, dict, array or struct at `key`.
+fn lcore_get_item(obj: &Value, key: &Value) -> Result<Value, LCoreError> {
+    match obj {
+        Value::String(v) => {
+            let index = match key {
+                Value::Int(i) => lcore_array_index(*i, v.chars().count())?,
+                _ => {
+                    return Err(LCoreError::TypeError(format!(
+                        "Cannot index String with {:?}",
+                        key
+                    )))
+                }
+            };
 
-    match name {
-        // Identifier
-        Value::Identifier(v) => {
-            symbol_table.insert(v.clone().to_string(), def);
+            let c = v.chars().nth(index).unwrap_or_default();
+            Ok(Value::String(Rc::new(c.to_string())))
         }
 
-        // Quoted Identifier
-        Value::Quote(v) => {
-            let mystr = v.as_identifier();
-            symbol_table.insert(mystr.clone().to_string(), def);
+        Value::Dict(_) | Value::Array(_) | Value::Struct { .. } => {
+            Ok(lcore_index(obj, key)?.clone())
         }
 
-        _ => (),
+        _ => Err(LCoreError::TypeError(format!(
+            "Cannot index {:?}",
+            obj
+        ))),
+    }
+}
+
+#[allow(clippy::manual_is_multiple_of)] // `is_multiple_of` needs Rust 1.87
+pub fn lcore_dict(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    if args.len() % 2 != 0 {
+        // crash(format!("Odd number of arguments passed to \"dict\""));
+        return Err(LCoreError::ArgumentError(
+            "Odd number of arguments passed to \"dict\"".to_string(),
+        ));
+    }
+
+    let mut dict = Dict::new();
+
+    for pair in args.chunks(2) {
+        let key = match &pair[0] {
+            Value::Quote(q) => lcore_dict_key(&lcore_expr_value(q)),
+            key => key.clone(),
+        };
+        lcore_expect_hashable(&key)?;
+        dict.insert(key, pair[1].clone());
+    }
+
+    Ok(Value::Dict(Rc::new(dict)))
+}
+
+pub fn lcore_import(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "import", 1, 1)?;
+
+    match &args[0] {
+        Value::String(file) => match lcore_import_file(
+            file.to_string(),
+            symbol_table,
+        ) {
+            Ok(module) => symbol_table.extend(module),
+            Err(err @ LCoreError::ExitError(_)) => return Err(err),
+            Err(err) => {
+                return Err(LCoreError::ImportError(format!(
+                    "Cannot import \"{}\": {}",
+                    file, err
+                )))
+            }
+        },
+        other => return Err(lcore_type_error(other, "a String", "import")),
     }
 
     Ok(Value::Null)
 }
 
-pub fn lcore_get(
+pub fn lcore_swap(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "swap", 3, 3)?;
+
+    let obj_id = lcore_expect_name(&args[0], "swap")?;
+    let value = &args[2];
+
+    // NOTE(pebaz): The index is a quoted list of values to index by:
+    // a[b][c][d][e]
+    lcore_expect_block(&args[1], "swap")?;
+    let indexers = lcore_index_path(&args[1]);
+    let (last, path) = lcore_split_path(&indexers, "swap")?;
+
+    lcore_with_name(symbol_table, obj_id, |obj| {
+        let mut current_obj = obj;
+
+        for indexer in path {
+            current_obj = lcore_index_mut(current_obj, indexer)?;
+        }
+
+        *lcore_index_mut(current_obj, last)? = value.clone();
+
+        Ok(Value::Null)
+    })
+}
+
+/// Like `swap`, but a key missing from a dict is added, along with a new dict
+/// for each missing key along the way.
+pub fn lcore_assoc(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
+    let args = args.as_array();
+    lcore_check_arity(args, "assoc", 3, 3)?;
+
+    let obj_id = lcore_expect_name(&args[0], "assoc")?;
+    let indexers = lcore_index_path(&args[1]);
+    let (last, path) = lcore_split_path(&indexers, "assoc")?;
+    let value = &args[2];
 
-    let obj = args
-        .next()
-        .expect("Not enough arguments on call to \"get\": 0/2");
-    let mut key = args
-        .next()
-        .expect("Not enough arguments on call to \"get\": 1/2");
+    lcore_with_name(symbol_table, obj_id, |obj| {
+        let mut current_obj = obj;
 
-    if let Value::Quote(q) = key {
-        key = q;
+        for indexer in path {
+            current_obj = lcore_index_or_insert(current_obj, indexer)?;
+        }
+
+        *lcore_index_or_insert(current_obj, last)? = value.clone();
+
+        Ok(Value::Null)
+    })
+}
+
+/// Remove a key from a dict held in a variable, returning its value. The
+/// keys after it keep their order.
+pub fn lcore_del(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "del", 2, 2)?;
+
+    let obj_id = lcore_expect_name(&args[0], "del")?;
+    let indexers = lcore_index_path(&args[1]);
+    let (last, path) = lcore_split_path(&indexers, "del")?;
+
+    lcore_with_name(symbol_table, obj_id, |obj| {
+        let mut current_obj = obj;
+
+        for indexer in path {
+            current_obj = lcore_index_mut(current_obj, indexer)?;
+        }
+
+        match current_obj {
+            Value::Dict(dict) => Rc::make_mut(dict)
+                .shift_remove(&lcore_dict_key(last))
+                .ok_or_else(|| {
+                    LCoreError::KeyError(format!(
+                        "No key named: {}",
+                        lcore_key_repr(last)
+                    ))
+                }),
+            other => Err(lcore_type_error(other, "a Dict", "del")),
+        }
+    })
+}
+
+/// Whether a key, or a path of keys such as `'[a 0 b]`, can be found in a
+/// dict, array or struct.
+pub fn lcore_has(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "has?", 2, 2)?;
+
+    let path = lcore_index_path(&args[1]);
+    match lcore_follow_path(&args[0], &path) {
+        Ok(_) => Ok(Value::Boolean(true)),
+        Err(LCoreError::KeyError(_)) | Err(LCoreError::IndexError(_)) => {
+            Ok(Value::Boolean(false))
+        }
+        Err(err) => Err(err),
     }
+}
 
-    match obj {
-        Value::Array(v) => {
-            if let Value::Int(index) = key {
-                if *index > v.len() as i64 {
-                    // crash(format!(
-                    // "Index out of bounds: got {} but len is {}", index,
-                    // v.len()));
+pub fn lcore_keys(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let dict = lcore_expect_dict_at(args.as_array(), "keys")?;
+    Ok(Value::Array(Rc::new(dict.keys().cloned().collect())))
+}
 
-                    return Err(LCoreError::ArgumentError(format!(
-                        "Index out of bounds: got {} but len is {}",
-                        index,
-                        v.len()
-                    )));
-                } else {
-                    let len = v.len() as i64;
-                    let mut idx = *index % len;
-                    if idx < 0 {
-                        idx += len
-                    }
-                    return Ok(v[idx as usize].clone());
-                }
-            } else {
-                // crash(format!("Cannot index Array with {:?}", key));
-                return Err(LCoreError::ArgumentError(format!(
-                    "Cannot index Array with {:?}",
-                    key
-                )));
-            }
+pub fn lcore_values(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let dict = lcore_expect_dict_at(args.as_array(), "values")?;
+    Ok(Value::Array(Rc::new(dict.values().cloned().collect())))
+}
+
+/// The entries of a dict as `[key value]` pairs.
+pub fn lcore_items(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let dict = lcore_expect_dict_at(args.as_array(), "items")?;
+    let items = dict
+        .iter()
+        .map(|(key, value)| {
+            Value::Array(Rc::new(vec![key.clone(), value.clone()]))
+        })
+        .collect();
+    Ok(Value::Array(Rc::new(items)))
+}
+
+/// A new dict with the entries of each dict in turn, so that later dicts
+/// win when they share a key.
+pub fn lcore_merge(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "merge", 1, usize::MAX)?;
+
+    let mut merged: Rc<Dict> = Rc::default();
+    for arg in args {
+        match arg {
+            Value::Dict(dict) if merged.is_empty() => merged = dict.clone(),
+            Value::Dict(dict) => Rc::make_mut(&mut merged)
+                .extend(dict.iter().map(|(k, v)| (k.clone(), v.clone()))),
+            other => return Err(lcore_type_error(other, "a Dict", "merge")),
         }
+    }
 
-        Value::Dict(v) => match key {
-            Value::Identifier(a) => {
-                return Ok(v
-                    .get(&Value::String(a.to_string()))
-                    .expect(&format!("No identifier key named: \"{}\"", a))
-                    .clone());
-            }
+    Ok(Value::Dict(merged))
+}
 
-            Value::String(a) => {
-                return Ok(v
-                    .get(key)
-                    .expect(&format!("No string key named: \"{}\"", a))
-                    .clone());
-            }
+/// A change to an array, shared by a builtin that returns a changed copy of
+/// the array and a `!` variant that changes a variable in place. It gets the
+/// arguments after the array and returns what the `!` variant returns.
+type ArrayOp =
+    fn(&mut Vec<Value>, &[Value], &str) -> Result<Value, LCoreError>;
+
+/// Run `op` on a copy of the array passed as the first argument.
+fn lcore_array_copy(
+    args: &[Value],
+    func_name: &str,
+    min: usize,
+    max: usize,
+    op: ArrayOp,
+) -> Result<Value, LCoreError> {
+    lcore_check_arity(args, func_name, min, max)?;
+    let mut items = lcore_expect_array(&args[0], func_name)?.clone();
+    op(&mut items, &args[1..], func_name)?;
+    Ok(Value::Array(Rc::new(items)))
+}
 
-            Value::Int(a) => {
-                return Ok(v
-                    .get(key)
-                    .expect(&format!("No int key named: {}", a))
-                    .clone());
-            }
+/// Run `op` on the array held in the variable named by the first argument.
+fn lcore_array_in_place(
+    args: &[Value],
+    symbol_table: &mut Environment,
+    func_name: &str,
+    min: usize,
+    max: usize,
+    op: ArrayOp,
+) -> Result<Value, LCoreError> {
+    lcore_check_arity(args, func_name, min, max)?;
+    let obj_id = lcore_expect_name(&args[0], func_name)?;
 
-            Value::Float(a) => {
-                return Ok(v
-                    .get(key)
-                    .expect(&format!("No float key named: {}", a))
-                    .clone());
-            }
+    lcore_with_name(symbol_table, obj_id, |obj| match obj {
+        Value::Array(items) => op(Rc::make_mut(items), &args[1..], func_name),
+        other => Err(lcore_type_error(other, "an Array", func_name)),
+    })
+}
+
+fn lcore_push_items(
+    items: &mut Vec<Value>,
+    args: &[Value],
+    _func_name: &str,
+) -> Result<Value, LCoreError> {
+    items.extend(args.iter().cloned());
+    Ok(Value::Null)
+}
+
+fn lcore_pop_item(
+    items: &mut Vec<Value>,
+    _args: &[Value],
+    func_name: &str,
+) -> Result<Value, LCoreError> {
+    items.pop().ok_or_else(|| {
+        LCoreError::IndexError(format!("\"{}\" got an empty array", func_name))
+    })
+}
+
+/// Insert an item so that it ends up at the given index, which may be one
+/// past the end of the array or count back from the end.
+fn lcore_insert_item(
+    items: &mut Vec<Value>,
+    args: &[Value],
+    func_name: &str,
+) -> Result<Value, LCoreError> {
+    let index = lcore_expect_int(&args[0], func_name)?;
+
+    // NOTE(pebaz): An item can go after the last one too, so `-1` means the
+    // very end.
+    let position = lcore_array_position(index, items.len() + 1)
+        .map_err(|_| lcore_index_error(index, items.len()))?;
+    items.insert(position, args[1].clone());
+    Ok(Value::Null)
+}
+
+fn lcore_remove_item(
+    items: &mut Vec<Value>,
+    args: &[Value],
+    func_name: &str,
+) -> Result<Value, LCoreError> {
+    let index = lcore_expect_int(&args[0], func_name)?;
+    let position = lcore_array_position(index, items.len())?;
+    Ok(items.remove(position))
+}
+
+// NOTE(pebaz): Takes a Vec rather than a slice so that it fits `ArrayOp`.
+#[allow(clippy::ptr_arg)]
+fn lcore_reverse_items(
+    items: &mut Vec<Value>,
+    _args: &[Value],
+    _func_name: &str,
+) -> Result<Value, LCoreError> {
+    items.reverse();
+    Ok(Value::Null)
+}
+
+pub fn lcore_push(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_array_copy(args, "push", 2, usize::MAX, lcore_push_items)
+}
+
+pub fn lcore_push_in_place(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    let op = lcore_push_items;
+    lcore_array_in_place(args, symbol_table, "push!", 2, usize::MAX, op)
+}
+
+pub fn lcore_pop(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_array_copy(args.as_array(), "pop", 1, 1, lcore_pop_item)
+}
+
+/// Remove the last item of an array held in a variable, returning it.
+pub fn lcore_pop_in_place(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_array_in_place(args, symbol_table, "pop!", 1, 1, lcore_pop_item)
+}
+
+pub fn lcore_insert(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_array_copy(args.as_array(), "insert", 3, 3, lcore_insert_item)
+}
+
+pub fn lcore_insert_in_place(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    let op = lcore_insert_item;
+    lcore_array_in_place(args, symbol_table, "insert!", 3, 3, op)
+}
+
+pub fn lcore_remove(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_array_copy(args.as_array(), "remove", 2, 2, lcore_remove_item)
+}
+
+/// Remove the item at an index of an array held in a variable, returning
+/// it.
+pub fn lcore_remove_in_place(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    let op = lcore_remove_item;
+    lcore_array_in_place(args, symbol_table, "remove!", 2, 2, op)
+}
+
+pub fn lcore_reverse(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_array_copy(args.as_array(), "reverse", 1, 1, lcore_reverse_items)
+}
+
+pub fn lcore_reverse_in_place(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    let op = lcore_reverse_items;
+    lcore_array_in_place(args, symbol_table, "reverse!", 1, 1, op)
+}
+
+/// The items of an array from a start index up to but not including an end
+/// index, which defaults to the end of the array. Negative indexes count back
+/// from the end, and indexes past either end stop at it.
+pub fn lcore_slice(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "slice", 2, 3)?;
+
+    let items = lcore_expect_array(&args[0], "slice")?;
+    let bound = |index: i64| {
+        if index < 0 {
+            (items.len() as i64 + index).max(0) as usize
+        } else {
+            (index as usize).min(items.len())
+        }
+    };
+
+    let start = bound(lcore_expect_int(&args[1], "slice")?);
+    let end = match args.get(2) {
+        Some(end) => bound(lcore_expect_int(end, "slice")?),
+        None => items.len(),
+    };
+
+    let slice = items.get(start..end).unwrap_or_default();
+    Ok(Value::Array(Rc::new(slice.to_vec())))
+}
+
+/// The index of the first item of an array equal to a value, or Null.
+pub fn lcore_index_of(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "index-of", 2, 2)?;
+
+    let items = lcore_expect_array(&args[0], "index-of")?;
+    Ok(items
+        .iter()
+        .position(|item| *item == args[1])
+        .map_or(Value::Null, |i| Value::Int(i as i64)))
+}
+
+pub fn lcore_contains(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "contains?", 2, 2)?;
+
+    let items = lcore_expect_array(&args[0], "contains?")?;
+    Ok(Value::Boolean(items.contains(&args[1])))
+}
+
+/// The first item of an array, or Null if it is empty.
+pub fn lcore_first(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "first", 1, 1)?;
 
-            Value::Boolean(a) => {
-                return Ok(v
-                    .get(key)
-                    .expect(&format!("No boolean key named: {}", a))
-                    .clone());
+    let items = lcore_expect_array(&args[0], "first")?;
+    Ok(items.first().cloned().unwrap_or(Value::Null))
+}
+
+/// Every item of an array but the first.
+pub fn lcore_rest(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "rest", 1, 1)?;
+
+    let items = lcore_expect_array(&args[0], "rest")?;
+    let rest = items.get(1..).unwrap_or_default();
+    Ok(Value::Array(Rc::new(rest.to_vec())))
+}
+
+/// The last item of an array, or Null if it is empty.
+pub fn lcore_last(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "last", 1, 1)?;
+
+    let items = lcore_expect_array(&args[0], "last")?;
+    Ok(items.last().cloned().unwrap_or(Value::Null))
+}
+
+/// The items of an array with any arrays in it, however deeply nested,
+/// replaced by their items.
+pub fn lcore_flatten(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    fn flatten_into(out: &mut Vec<Value>, items: &[Value]) {
+        for item in items {
+            match item {
+                Value::Array(inner) => flatten_into(out, inner),
+                item => out.push(item.clone()),
             }
+        }
+    }
 
-            _ => unreachable!(),
-        },
+    let args = args.as_array();
+    lcore_check_arity(args, "flatten", 1, 1)?;
+
+    let mut flat = Vec::new();
+    flatten_into(&mut flat, lcore_expect_array(&args[0], "flatten")?);
+    Ok(Value::Array(Rc::new(flat)))
+}
+
+/// Arrays of the items at each index of the given arrays, stopping at the
+/// end of the shortest one.
+pub fn lcore_zip(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "zip", 1, usize::MAX)?;
+
+    let arrays = args
+        .iter()
+        .map(|arg| lcore_expect_array(arg, "zip"))
+        .collect::<Result<Vec<_>, LCoreError>>()?;
+    let len = arrays.iter().map(|items| items.len()).min().unwrap_or(0);
+
+    let zipped = (0..len)
+        .map(|i| {
+            let row = arrays.iter().map(|items| items[i].clone()).collect();
+            Value::Array(Rc::new(row))
+        })
+        .collect();
+    Ok(Value::Array(Rc::new(zipped)))
+}
+
+/// Call a function with a single argument.
+fn lcore_call_with(
+    func: &Value,
+    arg: Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = Value::Array(Rc::new(vec![arg]));
+    lcore_call(func.clone(), args, symbol_table)
+}
+
+/// Call a function that should answer True or False for an item.
+fn lcore_test_item(
+    func: &Value,
+    item: &Value,
+    symbol_table: &mut Environment,
+    func_name: &str,
+) -> Result<bool, LCoreError> {
+    let result = lcore_call_with(func, item.clone(), symbol_table)?;
+    lcore_expect_bool(&result, func_name)
+}
+
+/// Call a function with the items of an array as its arguments.
+pub fn lcore_apply(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "apply", 2, 2)?;
+
+    lcore_expect_array(&args[1], "apply")?;
+    lcore_call(args[0].clone(), args[1].clone(), symbol_table)
+}
+
+pub fn lcore_map(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "map", 2, 2)?;
+
+    let items = lcore_expect_array(&args[1], "map")?;
+    let mapped = items
+        .iter()
+        .map(|item| lcore_call_with(&args[0], item.clone(), symbol_table))
+        .collect::<Result<Vec<Value>, LCoreError>>()?;
+    Ok(Value::Array(Rc::new(mapped)))
+}
+
+pub fn lcore_filter(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "filter", 2, 2)?;
+
+    let mut kept = Vec::new();
+    for item in lcore_expect_array(&args[1], "filter")?.iter() {
+        if lcore_test_item(&args[0], item, symbol_table, "filter")? {
+            kept.push(item.clone());
+        }
+    }
+    Ok(Value::Array(Rc::new(kept)))
+}
+
+/// Combine the items of an array with a function of two arguments, starting
+/// from the initial value if one is given before the array, or else from the
+/// first item.
+pub fn lcore_reduce(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "reduce", 2, 3)?;
+
+    let func = &args[0];
+    let items = lcore_expect_array(&args[args.len() - 1], "reduce")?;
+    let (mut result, rest) = match (args.len(), items.split_first()) {
+        (3, _) => (args[1].clone(), &items[..]),
+        (_, Some((first, rest))) => (first.clone(), rest),
+        (_, None) => {
+            return Err(LCoreError::ArgumentError(
+                "\"reduce\" got an empty array and no initial value"
+                    .to_string(),
+            ))
+        }
+    };
 
-        Value::String(v) => match key {
-            Value::Int(a) => {
-                println!("*******************************************");
-            }
+    for item in rest {
+        let pair = Value::Array(Rc::new(vec![result, item.clone()]));
+        result = lcore_call(func.clone(), pair, symbol_table)?;
+    }
+    Ok(result)
+}
 
-            _ => unreachable!(),
-        },
+/// Whether a function answers True for any item of an array, stopping at
+/// the first that it does.
+pub fn lcore_any(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "any?", 2, 2)?;
 
-        _ => (),
+    for item in lcore_expect_array(&args[1], "any?")?.iter() {
+        if lcore_test_item(&args[0], item, symbol_table, "any?")? {
+            return Ok(Value::Boolean(true));
+        }
     }
-
-    Ok(Value::Null)
+    Ok(Value::Boolean(false))
 }
 
-pub fn lcore_dict(
+/// Whether a function answers True for every item of an array, stopping at
+/// the first that it does not.
+pub fn lcore_all(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
     let args = args.as_array();
-    let mut args_iter = args.iter();
+    lcore_check_arity(args, "all?", 2, 2)?;
 
-    if args.len() % 2 != 0 {
-        // crash(format!("Odd number of arguments passed to \"dict\""));
-        return Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Odd number of arguments passed to \"dict\""
-        )));
+    for item in lcore_expect_array(&args[1], "all?")?.iter() {
+        if !lcore_test_item(&args[0], item, symbol_table, "all?")? {
+            return Ok(Value::Boolean(false));
+        }
     }
+    Ok(Value::Boolean(true))
+}
 
-    let mut dict: HashMap<Value, Value> = HashMap::new();
-
-    for i in 0..args.len() / 2 {
-        let key = args_iter.next().expect(&format!("NO KEY {}", i));
-        let value = args_iter.next().expect(&format!("NO VALUE {}", i));
+/// The first item of an array that a function answers True for, or Null.
+pub fn lcore_find(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "find", 2, 2)?;
 
-        if let Value::Quote(q) = key {
-            if let Value::Identifier(s) = *q.clone() {
-                dict.insert(Value::String(s), value.clone());
-            }
-        } else {
-            dict.insert(key.clone(), value.clone());
+    for item in lcore_expect_array(&args[1], "find")?.iter() {
+        if lcore_test_item(&args[0], item, symbol_table, "find")? {
+            return Ok(item.clone());
         }
     }
+    Ok(Value::Null)
+}
 
-    // dict.insert(Value::String(String::from("first name")), Value::Int(24));
-    // dict.insert(Value::String(String::from("last name")),
-    // Value::String(String::from("Wallace")));
+/// A sorted copy of an array in ascending order, as `lcore_sort_order`
+/// compares its items. Items that compare equal keep their order.
+pub fn lcore_sort(
+    args: &mut Value,
+    _symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+    lcore_check_arity(args, "sort", 1, 1)?;
 
-    Ok(Value::Dict(dict))
+    let items = lcore_expect_array(&args[0], "sort")?.clone();
+    let sorted = lcore_merge_sort(items, &mut |a: &Value, b: &Value| {
+        Ok(lcore_sort_order(a, b)? == Ordering::Less)
+    })?;
+    Ok(Value::Array(Rc::new(sorted)))
 }
 
-pub fn lcore_import(
+/// Like `sort`, but comparing the results of calling a function on each
+/// item, which is called once per item.
+pub fn lcore_sort_by(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let filename = match args.next() {
-        Some(e) => e,
-        None => {
-            return Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Not enough arguments on call to \"import\": 0/1"
-        )))
+    let args = args.as_array();
+    lcore_check_arity(args, "sort-by", 2, 2)?;
+
+    let keyed = lcore_expect_array(&args[1], "sort-by")?
+        .iter()
+        .map(|item| {
+            let key = lcore_call_with(&args[0], item.clone(), symbol_table)?;
+            Ok((key, item.clone()))
+        })
+        .collect::<Result<Vec<(Value, Value)>, LCoreError>>()?;
+
+    let sorted = lcore_merge_sort(keyed, &mut |a: &(Value, Value), b| {
+        Ok(lcore_sort_order(&a.0, &b.0)? == Ordering::Less)
+    })?;
+    let items = sorted.into_iter().map(|(_, item)| item).collect();
+    Ok(Value::Array(Rc::new(items)))
+}
+
+/// The order `sort` puts two values in. Numbers are compared by value, even
+/// an Int with a Float, strings and names are compared alphabetically and
+/// arrays are compared item by item. Values of other kinds cannot be sorted.
+fn lcore_sort_order(a: &Value, b: &Value) -> Result<Ordering, LCoreError> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => Ok(a.total_cmp(b)),
+        (Value::Int(a), Value::Float(b)) => Ok((*a as f64).total_cmp(b)),
+        (Value::Float(a), Value::Int(b)) => Ok(a.total_cmp(&(*b as f64))),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Value::Identifier(a), Value::Identifier(b)) => Ok(a.cmp(b)),
+        (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+        (Value::Null, Value::Null) => Ok(Ordering::Equal),
+        (Value::Array(a), Value::Array(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match lcore_sort_order(a, b)? {
+                    Ordering::Equal => (),
+                    order => return Ok(order),
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
         }
-    };
+        _ => Err(LCoreError::TypeError(format!(
+            "Cannot sort {:?} and {:?} together",
+            a, b
+        ))),
+    }
+}
 
-    if let Value::String(file) = filename {
-        symbol_table.extend(lcore_import_file(file.to_string()));
+/// A stable merge sort for comparisons that can fail, such as ones that
+/// call back into a script.
+fn lcore_merge_sort<T>(
+    mut items: Vec<T>,
+    less: &mut dyn FnMut(&T, &T) -> Result<bool, LCoreError>,
+) -> Result<Vec<T>, LCoreError> {
+    // NOTE(pebaz): `sort_by` cannot stop on an error, such as when a number
+    // is compared with a string.
+    if items.len() <= 1 {
+        return Ok(items);
     }
 
-    Ok(Value::Null)
+    let right = items.split_off(items.len() / 2);
+    let mut left = lcore_merge_sort(items, less)?.into_iter().peekable();
+    let mut right = lcore_merge_sort(right, less)?.into_iter().peekable();
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Only take from the right when strictly less to keep the sort
+        // stable
+        if less(r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
 }
 
-pub fn lcore_swap(
-    args: &mut Value,
+/// Run `f` on the variable named `name`, which must already exist.
+fn lcore_with_name(
     symbol_table: &mut Environment,
+    name: Symbol,
+    f: impl FnOnce(&mut Value) -> Result<Value, LCoreError>,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let obj_id = args.next().unwrap().as_value().as_identifier();
-    let index = args.next().unwrap();
-    let value = args.next().unwrap();
+    symbol_table.with_value(name, f).unwrap_or_else(|| {
+        Err(LCoreError::NameError(format!(
+            "Cannot lookup name: \"{}\"",
+            name
+        )))
+    })
+}
 
-    // TODO(pebaz): The `index` is a quoted list of values to index by:
-    // a[b][c][d][e]
+/// The keys to index by one after another: either a quoted block such as
+/// `'[a 0 b]` or a single key.
+fn lcore_index_path(index: &Value) -> Vec<Value> {
+    match index {
+        Value::Quote(q) => match &**q {
+            Expr::Array(_, v) => v.iter().map(lcore_expr_value).collect(),
+            other => vec![lcore_expr_value(other)],
+        },
+        key => vec![key.clone()],
+    }
+}
 
-    // println!("{}, {:?}, {:?}", obj_id, index, value);
+/// Split a path into its last key and the keys leading up to it.
+fn lcore_split_path<'a>(
+    path: &'a [Value],
+    func_name: &str,
+) -> Result<(&'a Value, &'a [Value]), LCoreError> {
+    path.split_last().ok_or_else(|| {
+        LCoreError::ArgumentError(format!(
+            "\"{}\" needs at least one index",
+            func_name
+        ))
+    })
+}
 
-    if let Some(obj) = symbol_table.get(obj_id.to_string()) {
-        let mut current_obj = obj;
+/// Index into a value with each key of a path in turn.
+fn lcore_follow_path<'a>(
+    obj: &'a Value,
+    path: &[Value],
+) -> Result<&'a Value, LCoreError> {
+    path.iter().try_fold(obj, |obj, key| lcore_index(obj, key))
+}
 
-        let indexers = index.as_value().as_array();
-        for i in 0..indexers.len() - 1 {
-            // for indexer in index.as_value().as_array() {
-            let indexer = &indexers[i];
-
-            match current_obj {
-                Value::Dict(ref mut v) => {
-                    // current_obj = v[indexer]
-                    if let Value::Identifier(s) = indexer {
-                        current_obj =
-                            v.get_mut(&Value::String(s.to_string())).unwrap();
-                    } else {
-                        current_obj = v.get_mut(&indexer).unwrap();
-                    }
-                }
+/// The dict passed as the first argument, or the one found within it by
+/// following the path given as an optional second argument.
+fn lcore_expect_dict_at<'a>(
+    args: &'a [Value],
+    func_name: &str,
+) -> Result<&'a Dict, LCoreError> {
+    lcore_check_arity(args, func_name, 1, 2)?;
+
+    let path = args.get(1).map(lcore_index_path).unwrap_or_default();
+    match lcore_follow_path(&args[0], &path)? {
+        Value::Dict(dict) => Ok(dict),
+        other => Err(lcore_type_error(other, "a Dict", func_name)),
+    }
+}
 
-                Value::Array(ref mut v) => {
-                    // current_obj = v[indexer]
-
-                    if let Value::Int(i) = indexer {
-                        if *i > v.len() as i64 {
-                            return Err(LCoreError::ArgumentError(format!(
-                                "Index out of bounds: got {} but len is {}",
-                                i,
-                                v.len()
-                            )));
-                        } else {
-                            let len = v.len() as i64;
-                            let mut idx = i % len;
-                            if idx < 0 {
-                                idx += len
-                            }
-                            current_obj = v.get_mut(idx as usize).unwrap();
-                        }
-                    } else {
-                        return Err(LCoreError::IndexError(
-                            "IndexError: Cannot index array with non-int"
-                                .to_string(),
-                        ));
-                    }
-                }
+/// Find the element of a dict, array or struct that `indexer` refers to.
+fn lcore_index<'a>(
+    obj: &'a Value,
+    indexer: &Value,
+) -> Result<&'a Value, LCoreError> {
+    match obj {
+        Value::Dict(v) => v.get(&lcore_dict_key(indexer)).ok_or_else(|| {
+            LCoreError::KeyError(format!(
+                "No key named: {}",
+                lcore_key_repr(indexer)
+            ))
+        }),
 
-                _ => unreachable!(),
+        Value::Array(v) => {
+            if let Value::Int(i) = indexer {
+                let idx = lcore_array_index(*i, v.len())?;
+                Ok(&v[idx])
+            } else {
+                Err(LCoreError::TypeError(
+                    "Cannot index array with non-int".to_string(),
+                ))
             }
         }
 
-        let indexer = &indexers[indexers.len() - 1];
-        match current_obj {
-            Value::Dict(ref mut v) => {
-                if let Value::Identifier(s) = indexer {
-                    *v.get_mut(&Value::String(s.to_string())).unwrap() =
-                        value.clone();
-                } else {
-                    *v.get_mut(&indexer).unwrap() = value.clone();
-                }
-            }
+        Value::Struct { kind, fields } => {
+            let i = lcore_field_index(kind, indexer)?;
+            Ok(&fields[i])
+        }
 
-            Value::Array(ref mut v) => {
-                if let Value::Int(i) = indexer {
-                    if *i > v.len() as i64 {
-                        return Err(LCoreError::ArgumentError(format!(
-                            "Index out of bounds: got {} but len is {}",
-                            i,
-                            v.len()
-                        )));
-                    } else {
-                        let len = v.len() as i64;
-                        let mut idx = i % len;
-                        if idx < 0 {
-                            idx += len
-                        }
-                        *v.get_mut(idx as usize).unwrap() = value.clone();
-                    }
-                } else {
-                    return Err(LCoreError::IndexError(
-                        "IndexError: Cannot index array with non-int"
-                            .to_string(),
-                    ));
-                }
+        _ => Err(LCoreError::TypeError(format!("Cannot index {:?}", obj))),
+    }
+}
+
+/// Like `lcore_index` but for changing the element, first copying a dict or
+/// array that is shared with another value.
+fn lcore_index_mut<'a>(
+    obj: &'a mut Value,
+    indexer: &Value,
+) -> Result<&'a mut Value, LCoreError> {
+    match obj {
+        Value::Dict(v) => Rc::make_mut(v)
+            .get_mut(&lcore_dict_key(indexer))
+            .ok_or_else(|| {
+                LCoreError::KeyError(format!(
+                    "No key named: {}",
+                    lcore_key_repr(indexer)
+                ))
+            }),
+
+        Value::Array(v) => {
+            if let Value::Int(i) = indexer {
+                let idx = lcore_array_index(*i, v.len())?;
+                Ok(&mut Rc::make_mut(v)[idx])
+            } else {
+                Err(LCoreError::TypeError(
+                    "Cannot index array with non-int".to_string(),
+                ))
             }
+        }
 
-            _ => unreachable!(),
+        Value::Struct { kind, fields } => {
+            let i = lcore_field_index(kind, indexer)?;
+            Ok(&mut fields[i])
         }
 
-        // lcore_print_value(
-        // 	&mut Value::Array(vec![
-        // 		symbol_table.get(obj_id.to_string()).unwrap().clone()
-        // 	])
-        // );
+        _ => Err(LCoreError::TypeError(format!(
+            "Cannot index {:?}",
+            obj
+        ))),
     }
+}
 
-    Ok(Value::Null)
+/// Like `lcore_index_mut`, but a key missing from a dict is added with an
+/// empty dict as its value.
+fn lcore_index_or_insert<'a>(
+    obj: &'a mut Value,
+    indexer: &Value,
+) -> Result<&'a mut Value, LCoreError> {
+    match obj {
+        Value::Dict(v) => {
+            let key = lcore_dict_key(indexer);
+            lcore_expect_hashable(&key)?;
+            Ok(Rc::make_mut(v)
+                .entry(key)
+                .or_insert_with(|| Value::Dict(Rc::default())))
+        }
+        obj => lcore_index_mut(obj, indexer),
+    }
+}
+
+/// Find the position of an index within an array. Negative indexes count
+/// back from the end, wrapping around as many times as needed.
+fn lcore_array_index(index: i64, len: usize) -> Result<usize, LCoreError> {
+    if len == 0 || index >= len as i64 {
+        return Err(lcore_index_error(index, len));
+    }
+
+    Ok(index.rem_euclid(len as i64) as usize)
+}
+
+/// Find the position of an index within an array for changing it. Negative
+/// indexes count back from the end once, so that an index that is out of
+/// range never changes the wrong item.
+fn lcore_array_position(index: i64, len: usize) -> Result<usize, LCoreError> {
+    let position = if index < 0 { index + len as i64 } else { index };
+
+    if position < 0 || position >= len as i64 {
+        return Err(lcore_index_error(index, len));
+    }
+
+    Ok(position as usize)
+}
+
+fn lcore_index_error(index: i64, len: usize) -> LCoreError {
+    LCoreError::IndexError(format!(
+        "Index out of bounds: got {} but len is {}",
+        index, len
+    ))
+}
+
+/// Identifiers used as dict keys stand for the string of the same name.
+fn lcore_dict_key(key: &Value) -> Value {
+    match key {
+        Value::Identifier(s) => Value::String(Rc::new(s.to_string())),
+        _ => key.clone(),
+    }
+}
+
+fn lcore_expect_hashable(key: &Value) -> Result<(), LCoreError> {
+    if key.is_hashable() {
+        Ok(())
+    } else {
+        Err(LCoreError::TypeError(format!(
+            "Cannot use {:?} as a dict key",
+            key
+        )))
+    }
+}
+
+/// Describe a dict key for an error message.
+fn lcore_key_repr(key: &Value) -> String {
+    match key {
+        Value::Identifier(s) => format!("\"{}\"", s),
+        Value::String(s) => format!("\"{}\"", s),
+        Value::Int(i) => format!("{}", i),
+        Value::Float(f) => format!("{}", f),
+        Value::Boolean(b) => (if *b { "True" } else { "False" }).to_string(),
+        _ => format!("{:?}", key),
+    }
+}
+
+/// Find the position of the field named by `indexer` within a struct.
+fn lcore_field_index(
+    kind: &StructType,
+    indexer: &Value,
+) -> Result<usize, LCoreError> {
+    let field = match indexer {
+        Value::Identifier(s) => s,
+        Value::String(s) => s,
+        _ => {
+            return Err(LCoreError::TypeError(
+                "Cannot index struct with non-identifier"
+                    .to_string(),
+            ))
+        }
+    };
+
+    kind.field_index(field).ok_or_else(|| {
+        LCoreError::KeyError(format!(
+            "\"{}\" has no field named \"{}\"",
+            kind.name, field
+        ))
+    })
 }
 
 pub fn lcore_len(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let arg = match args.next() {
-        Some(e) => e,
-        None => {
-            return Err(LCoreError::ArgumentError(format!(
-                "ArgumentError: Not enough arguments on call to \"len\": 0/1"
-            )))
-        }
-    };
+    let args = args.as_array();
+    lcore_check_arity(args, "len", 1, 1)?;
+    let arg = &args[0];
 
-    return match arg {
+    match arg {
         Value::Array(v) => Ok(Value::Int(v.len() as i64)),
         Value::Dict(v) => Ok(Value::Int(v.len() as i64)),
         Value::String(v) => Ok(Value::Int(v.len() as i64)),
-        Value::Quote(v) => Ok(Value::Int(1)),
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: {:?} has no length",
-            arg
-        ))),
-    };
+        Value::Quote(_) => Ok(Value::Int(1)),
+        Value::Lambda(v) => Ok(Value::Int(v.code.params.len() as i64)),
+        Value::Macro(v) => Ok(Value::Int(v.code.params.len() as i64)),
+        Value::Struct { fields, .. } => Ok(Value::Int(fields.len() as i64)),
+        _ => Err(LCoreError::TypeError(format!("{:?} has no length", arg))),
+    }
 }
 
+#[allow(clippy::only_used_in_recursion)] // The signature of a `NativeFn`
 pub fn lcore_equals(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "=", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Null, Value::Null) => Ok(Value::Boolean(true)),
@@ -590,33 +1844,52 @@ pub fn lcore_equals(
         }
         (Value::Dict(a), Value::Dict(b)) => Ok(Value::Boolean(a == b)),
         (Value::Array(a), Value::Array(b)) => Ok(Value::Boolean(a == b)),
+        (Value::Lambda(a), Value::Lambda(b)) => {
+            Ok(Value::Boolean(Rc::ptr_eq(a, b)))
+        }
+        (Value::Macro(a), Value::Macro(b)) => {
+            Ok(Value::Boolean(Rc::ptr_eq(a, b)))
+        }
+        (Value::Type(a), Value::Type(b)) => {
+            Ok(Value::Boolean(Rc::ptr_eq(a, b)))
+        }
+        (Value::Struct { .. }, Value::Struct { .. }) => {
+            Ok(Value::Boolean(a == b))
+        }
+        // NOTE(pebaz): A quoted call stands for itself, so it is compared
+        // as code rather than as the value it would stand for.
+        (Value::Quote(a), Value::Quote(b))
+            if lcore_is_code(a) || lcore_is_code(b) =>
+        {
+            Ok(Value::Boolean(a == b))
+        }
         (Value::Quote(a), Value::Quote(b)) => lcore_equals(
-            &mut Value::Array(vec![*a.clone(), *b.clone()]),
+            &mut Value::Array(Rc::new(vec![
+                lcore_expr_value(a),
+                lcore_expr_value(b),
+            ])),
             symbol_table,
         ),
 
-        (Value::Func { f: a }, Value::Func { f: b }) => {
-            // TODO(pebaz): Allow comparing builtin funcs?
-            let addr_a = format!("{:p}", &*a);
-            let addr_b = format!("{:p}", &*b);
-            println!("HERE: {}, {}", addr_a, addr_b);
-            Ok(Value::Boolean(addr_a == addr_b))
+        (Value::Func(a), Value::Func(b)) => {
+            Ok(Value::Boolean(Rc::ptr_eq(a, b)))
         }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Type mismatch ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Type mismatch ({:?} and {:?})",
             a, b
         ))),
     }
 }
 
+#[allow(clippy::only_used_in_recursion)] // The signature of a `NativeFn`
 pub fn lcore_not_equals(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "!=", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Null, Value::Null) => Ok(Value::Boolean(false)),
@@ -629,33 +1902,52 @@ pub fn lcore_not_equals(
         }
         (Value::Dict(a), Value::Dict(b)) => Ok(Value::Boolean(a != b)),
         (Value::Array(a), Value::Array(b)) => Ok(Value::Boolean(a != b)),
+        (Value::Lambda(a), Value::Lambda(b)) => {
+            Ok(Value::Boolean(!Rc::ptr_eq(a, b)))
+        }
+        (Value::Macro(a), Value::Macro(b)) => {
+            Ok(Value::Boolean(!Rc::ptr_eq(a, b)))
+        }
+        (Value::Type(a), Value::Type(b)) => {
+            Ok(Value::Boolean(!Rc::ptr_eq(a, b)))
+        }
+        (Value::Struct { .. }, Value::Struct { .. }) => {
+            Ok(Value::Boolean(a != b))
+        }
+        // NOTE(pebaz): A quoted call stands for itself, so it is compared
+        // as code rather than as the value it would stand for.
+        (Value::Quote(a), Value::Quote(b))
+            if lcore_is_code(a) || lcore_is_code(b) =>
+        {
+            Ok(Value::Boolean(a != b))
+        }
         (Value::Quote(a), Value::Quote(b)) => lcore_not_equals(
-            &mut Value::Array(vec![*a.clone(), *b.clone()]),
+            &mut Value::Array(Rc::new(vec![
+                lcore_expr_value(a),
+                lcore_expr_value(b),
+            ])),
             symbol_table,
         ),
 
-        (Value::Func { f: a }, Value::Func { f: b }) => {
-            // TODO(pebaz): Allow comparing builtin funcs?
-            let addr_a = format!("{:p}", &*a);
-            let addr_b = format!("{:p}", &*b);
-            println!("HERE: {}, {}", addr_a, addr_b);
-            Ok(Value::Boolean(addr_a != addr_b))
+        (Value::Func(a), Value::Func(b)) => {
+            Ok(Value::Boolean(!Rc::ptr_eq(a, b)))
         }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Type mismatch ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Type mismatch ({:?} and {:?})",
             a, b
         ))),
     }
 }
 
+#[allow(clippy::only_used_in_recursion)] // The signature of a `NativeFn`
 pub fn lcore_less_than(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "<", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Int(a), Value::Int(b)) => Ok(Value::Boolean(a < b)),
@@ -672,13 +1964,20 @@ pub fn lcore_less_than(
         (Value::Array(a), Value::Array(b)) => {
             Ok(Value::Boolean(a.len() < b.len()))
         }
-        (Value::Quote(a), Value::Quote(b)) => lcore_less_than(
-            &mut Value::Array(vec![*a.clone(), *b.clone()]),
-            symbol_table,
-        ),
+        (Value::Quote(a), Value::Quote(b))
+            if !lcore_is_code(a) && !lcore_is_code(b) =>
+        {
+            lcore_less_than(
+                &mut Value::Array(Rc::new(vec![
+                    lcore_expr_value(a),
+                    lcore_expr_value(b),
+                ])),
+                symbol_table,
+            )
+        }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid Argument Types ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid Argument Types ({:?} and {:?})",
             a, b
         ))),
     }
@@ -686,17 +1985,17 @@ pub fn lcore_less_than(
 
 pub fn lcore_logical_or(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "or", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Boolean(a), Value::Boolean(b)) => Ok(Value::Boolean(a | b)),
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Not booleans ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Not booleans ({:?} and {:?})",
             a, b
         ))),
     }
@@ -704,17 +2003,17 @@ pub fn lcore_logical_or(
 
 pub fn lcore_logical_and(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "and", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Boolean(a), Value::Boolean(b)) => Ok(Value::Boolean(a & b)),
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Not booleans ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Not booleans ({:?} and {:?})",
             a, b
         ))),
     }
@@ -722,52 +2021,56 @@ pub fn lcore_logical_and(
 
 pub fn lcore_logical_not(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "not", 1, 1)?;
+    let a = &args[0];
 
     if let Value::Boolean(b) = a {
-        return Ok(Value::Boolean(!b));
+        Ok(Value::Boolean(!b))
     } else {
-        return Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Not a boolean ({:?})",
-            a
-        )));
+        Err(LCoreError::TypeError(format!("Not a boolean ({:?})", a)))
     }
 }
 
 pub fn lcore_to_str(
-    args: &mut Value,
-    symbol_table: &mut Environment,
+    _args: &mut Value,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    Ok(Value::String(String::from("LambdaCore String!")))
+    Ok(Value::String(Rc::new(String::from("LambdaCore String!"))))
 }
 
 pub fn lcore_add(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "+", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
-        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
+        (Value::Int(a), Value::Int(b)) => {
+            lcore_int_result(a.checked_add(*b), "+")
+        }
         (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
         (Value::String(a), Value::String(b)) => {
-            let mut result = a.clone();
+            let mut result = String::with_capacity(a.len() + b.len());
+            result.push_str(a);
             result.push_str(b);
-            Ok(Value::String(result.to_string()))
+            Ok(Value::String(Rc::new(result)))
         }
         (Value::Array(a), Value::Array(b)) => {
-            let mut result = a.clone();
+            // NOTE(pebaz): Only the items are copied, and copying an item
+            // that holds a collection just shares it.
+            let mut result = Vec::with_capacity(a.len() + b.len());
+            result.extend(a.iter().cloned());
             result.extend(b.iter().cloned());
-            Ok(Value::Array(result))
+            Ok(Value::Array(Rc::new(result)))
         }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid operands ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid operands ({:?} and {:?})",
             a, b
         ))),
     }
@@ -775,18 +2078,20 @@ pub fn lcore_add(
 
 pub fn lcore_sub(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "-", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
-        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
+        (Value::Int(a), Value::Int(b)) => {
+            lcore_int_result(a.checked_sub(*b), "-")
+        }
         (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid operands ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid operands ({:?} and {:?})",
             a, b
         ))),
     }
@@ -794,52 +2099,90 @@ pub fn lcore_sub(
 
 pub fn lcore_mul(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "*", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
-        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
+        (Value::Int(a), Value::Int(b)) => {
+            lcore_int_result(a.checked_mul(*b), "*")
+        }
         (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
+
+        // NOTE(pebaz): Repeating something a negative number of times gives
+        // an empty result, and so does repeating nothing any number of times.
         (Value::String(a), Value::Int(b)) => {
-            let mut result = a.clone();
-            for i in 0..*b - 1 {
+            let times = lcore_repeat_times(a.is_empty(), b);
+            let mut result = String::new();
+            a.len()
+                .checked_mul(times)
+                .and_then(|len| result.try_reserve(len).ok())
+                .ok_or_else(|| lcore_repeat_error(b))?;
+
+            for _ in 0..times {
                 result.push_str(a);
             }
-            Ok(Value::String(result.to_string()))
+            Ok(Value::String(Rc::new(result)))
         }
         (Value::Array(a), Value::Int(b)) => {
-            let aa = a.clone();
-            let mut result = a.clone();
-            for i in 0..*b - 1 {
-                result.extend(aa.iter().cloned());
+            let times = lcore_repeat_times(a.is_empty(), b);
+            let mut result = Vec::new();
+            a.len()
+                .checked_mul(times)
+                .and_then(|len| result.try_reserve(len).ok())
+                .ok_or_else(|| lcore_repeat_error(b))?;
+
+            for _ in 0..times {
+                result.extend(a.iter().cloned());
             }
-            Ok(Value::Array(result))
+            Ok(Value::Array(Rc::new(result)))
         }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid operands ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid operands ({:?} and {:?})",
             a, b
         ))),
     }
 }
 
+/// How many times `*` should repeat a string or array, so that nothing is
+/// done for a negative count or for an empty string or array.
+fn lcore_repeat_times(empty: bool, times: &i64) -> usize {
+    if empty {
+        0
+    } else {
+        usize::try_from(*times).unwrap_or(0)
+    }
+}
+
+fn lcore_repeat_error(times: &i64) -> LCoreError {
+    LCoreError::ArgumentError(format!(
+        "Cannot repeat {} times",
+        times
+    ))
+}
+
 pub fn lcore_div(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "/", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
-        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
+        (Value::Int(_), Value::Int(0)) => Err(LCoreError::ZeroDivisionError(
+            "Division by zero".to_string(),
+        )),
+        (Value::Int(a), Value::Int(b)) => {
+            lcore_int_result(a.checked_div(*b), "/")
+        }
         (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid operands ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid operands ({:?} and {:?})",
             a, b
         ))),
     }
@@ -847,19 +2190,20 @@ pub fn lcore_div(
 
 pub fn lcore_exponent(
     args: &mut Value,
-    symbol_table: &mut Environment,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut args = args.as_array().iter();
-    let a = args.next().unwrap();
-    let b = args.next().unwrap();
+    let args = args.as_array();
+    lcore_check_arity(args, "**", 2, 2)?;
+    let (a, b) = (&args[0], &args[1]);
 
     match (a, b) {
         (Value::Int(a), Value::Int(b)) => {
             if *b >= 0 {
-                Ok(Value::Int(i64::pow(*a, *b as u32)))
+                let exp = u32::try_from(*b).unwrap_or(u32::MAX);
+                lcore_int_result(a.checked_pow(exp), "**")
             } else {
                 Err(LCoreError::ArgumentError(format!(
-                    "ArgumentError: Negative exponent ({})",
+                    "Negative exponent ({})",
                     b
                 )))
             }
@@ -868,8 +2212,8 @@ pub fn lcore_exponent(
             Ok(Value::Float(f64::powf(*a, *b)))
         }
 
-        _ => Err(LCoreError::ArgumentError(format!(
-            "ArgumentError: Invalid operands ({:?} and {:?})",
+        _ => Err(LCoreError::TypeError(format!(
+            "Invalid operands ({:?} and {:?})",
             a, b
         ))),
     }
@@ -879,116 +2223,175 @@ pub fn lcore_if(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
+    let tail = symbol_table.take_tail_position();
     let args = args.as_array();
-    let mut vecargs = args.iter();
-    let condition = vecargs.next().unwrap();
-    let block_true = vecargs.next().unwrap();
+    lcore_check_arity(args, "if", 2, 3)?;
 
-    let block_false = if args.len() > 2 {
-        vecargs.next().unwrap()
-    } else {
-        &Value::Null
-    };
+    let condition = lcore_expect_bool(&args[0], "if")?;
+    let block_true = lcore_expect_block(&args[1], "if")?;
 
-    if *condition.as_bool() {
-        let element = block_true.as_value();
-        let result = lcore_interpret_array(element, symbol_table);
-        // let mut result = result.ok().unwrap();
+    if condition {
+        return lcore_interpret_block(block_true, symbol_table, tail);
+    } else if let Some(block_false) = args.get(2) {
+        let element = lcore_expect_block(block_false, "if")?;
+        return lcore_interpret_block(element, symbol_table, tail);
+    }
 
-        let mut result = match result {
-            Err(err) => return Err(err),
-            Ok(yeah) => yeah,
-        };
+    Ok(Value::Null)
+}
 
-        // TODO(pebaz): Check for errors on the result
+pub fn lcore_sel(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let tail = symbol_table.take_tail_position();
+    let args = args.as_array();
+    lcore_check_arity(args, "sel", 1, usize::MAX)?;
+    let mut vecargs = args.iter();
+    let compare = &args[0];
+    vecargs.next();
 
-        if let Value::Array(ref mut r) = result {
-            let ret = r.pop().unwrap();
-            // lcore_print_value(&mut Value::Array(vec![ret]));
-            // println!("  <--");
-            return Ok(ret);
+    while let (Some(value), Some(code)) = (vecargs.next(), vecargs.next()) {
+        if lcore_sel_matches(compare, value, symbol_table) {
+            return lcore_interpret_block(
+                lcore_expect_block(code, "sel")?,
+                symbol_table,
+                tail,
+            );
         }
-    } else {
-        if let Value::Null = block_false {
-        } else {
-            let element = block_false.as_value();
-            let result = lcore_interpret_array(element, symbol_table);
-            //let mut result = result.ok().unwrap();
+    }
 
-            let mut result = match result {
-                Err(err) => return Err(err),
-                Ok(yeah) => yeah,
-            };
+    Ok(Value::Null)
+}
 
-            if let Value::Array(ref mut r) = result {
-                let ret = r.pop().unwrap();
-                return Ok(ret);
+/// Check if a value given to `sel` selects its block, either by being equal
+/// to the value being compared or by being `'default`.
+pub fn lcore_sel_matches(
+    compare: &Value,
+    value: &Value,
+    symbol_table: &mut Environment,
+) -> bool {
+    // NOTE(pebaz): Check for `'default` block
+    if let Value::Quote(q) = value {
+        if let Expr::Symbol(_, s) = &**q {
+            if &*s.name() == "default" {
+                return true;
             }
         }
     }
 
-    Ok(Value::Null)
+    let res = lcore_equals(
+        &mut Value::Array(Rc::new(vec![compare.clone(), value.clone()])),
+        symbol_table,
+    );
+    matches!(res, Ok(Value::Boolean(true)))
 }
 
-pub fn lcore_sel(
+/// Raise an error of the given kind, or re-raise an error caught by `try`.
+pub fn lcore_raise(
     args: &mut Value,
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
     let args = args.as_array();
-    let mut vecargs = args.iter();
-    let compare = vecargs.next().unwrap();
 
-    while let (Some(value), Some(code)) = (vecargs.next(), vecargs.next()) {
-        // NOTE(pebaz): Check for `'default` block
-        if let Value::Quote(q) = value {
-            if let Value::Identifier(s) = value.as_value() {
-                if s == "default" {
-                    let result =
-                        lcore_interpret_array(code.as_value(), symbol_table);
-                    /*if let Err(..) = result {
-                        return result;
-                    }
-                    break;*/
-                    match result {
-                        Err(..) => return result,
-                        Ok(mut unwraped_result) => {
-                            if let Value::Array(ref mut r) = unwraped_result {
-                                let ret = r.pop().unwrap();
-                                return Ok(ret);
-                            }
-                        }
-                    }
-                }
+    match args.as_slice() {
+        [Value::Quote(kind), Value::String(message)] => match &**kind {
+            Expr::Symbol(_, kind) => {
+                Err(LCoreError::from_kind(&kind.name(), message))
             }
-        }
+            _ => Err(LCoreError::ArgumentError(
+                "Error kind must be a quoted identifier".to_string(),
+            )),
+        },
 
-        let res = lcore_equals(
-            &mut Value::Array(vec![compare.clone(), value.clone()]),
-            symbol_table,
-        );
-        if let Ok(res) = res {
-            if *res.as_bool() {
-                let result =
-                    lcore_interpret_array(code.as_value(), symbol_table);
-                /*if let Err(..) = result {
-                    return result;
-                }
-                break;*/
-                
-                match result {
-                    Err(..) => return result,
-                    Ok(mut unwraped_result) => {
-                        if let Value::Array(ref mut r) = unwraped_result {
-                            let ret = r.pop().unwrap();
-                            return Ok(ret);
-                        }
-                    }
+        [Value::Struct { kind, fields }]
+            if Rc::ptr_eq(kind, &symbol_table.error_type) =>
+        {
+            match (&fields[0], &fields[1]) {
+                (Value::String(kind), Value::String(message)) => {
+                    Err(LCoreError::from_kind(kind, message))
                 }
+                _ => Err(LCoreError::TypeError(
+                    "Error kind and message must be strings".to_string(),
+                )),
             }
         }
+
+        _ => Err(LCoreError::ArgumentError(
+            "\"raise\" takes a quoted kind and a message or an error"
+                .to_string(),
+        )),
     }
+}
 
-    Ok(Value::Null)
+/// Run a block of code for `try`. A call in tail position would escape the
+/// error handler, so it is made here and its value returned with `ret`.
+fn lcore_try_block(
+    block: &Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let block = match block {
+        Value::Quote(q) => q,
+        _ => {
+            return Err(LCoreError::ArgumentError(
+                "\"try\" expects quoted blocks of code".to_string(),
+            ))
+        }
+    };
+
+    match lcore_interpret_block(block, symbol_table, false) {
+        Err(LCoreError::TailCallError) => {
+            let (func, args) = symbol_table.tail_call.take().unwrap();
+            let value = lcore_call(func, args, symbol_table)?;
+            symbol_table.push_ret(value);
+            LCoreError::Return()
+        }
+        result => result,
+    }
+}
+
+/// Run a block of code, binding any error it raises to a name and running a
+/// handler block in its place. An optional last block is always run.
+pub fn lcore_try(
+    args: &mut Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let args = args.as_array();
+
+    lcore_check_arity(args, "try", 3, 4)?;
+
+    let name = lcore_expect_name(&args[1], "try")?.to_string();
+
+    let result = match lcore_try_block(&args[0], symbol_table) {
+        Err(err) if !err.is_control_flow() => {
+            // NOTE(pebaz): The error was handled so where it came from no
+            // longer matters.
+            symbol_table.take_traceback();
+
+            let error = Value::Struct {
+                kind: symbol_table.error_type.clone(),
+                fields: vec![
+                    Value::String(Rc::new(err.kind().to_string())),
+                    Value::String(Rc::new(err.message().to_string())),
+                ],
+            };
+
+            // NOTE(pebaz): The error is only named in the handler so that it
+            // does not replace a variable of the same name outside of it.
+            symbol_table.push();
+            symbol_table.define(name, error);
+            let handled = lcore_try_block(&args[2], symbol_table);
+            symbol_table.pop();
+            handled
+        }
+        result => result,
+    };
+
+    if let Some(finally) = args.get(3) {
+        lcore_try_block(finally, symbol_table)?;
+    }
+
+    result
 }
 
 pub fn lcore_return(
@@ -996,9 +2399,9 @@ pub fn lcore_return(
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
     let args = args.as_array();
-    let mut vecargs = args.iter();
-    let value = vecargs.next().unwrap();
-    symbol_table.push_ret(value.clone());
+    lcore_check_arity(args, "ret", 0, 1)?;
+    let value = args.first().cloned().unwrap_or(Value::Null);
+    symbol_table.push_ret(value);
     // Ok(Value::Null)
     // Err(LCoreError::LambdaCoreError(format!("BREAK")))
     // LCoreError::Return(value.clone())
@@ -1006,61 +2409,95 @@ pub fn lcore_return(
 }
 
 pub fn lcore_break(
-    args: &mut Value,
-    symbol_table: &mut Environment,
+    _args: &mut Value,
+    _symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
     LCoreError::Break()
 }
 
+/// Make a builtin available to scripts under `name`.
+pub fn lcore_define_builtin(
+    symbol_table: &mut Environment,
+    name: &str,
+    f: NativeFn,
+) {
+    let func = NativeFunc::new(name, f);
+    symbol_table.insert(name, Value::Func(Rc::new(func)));
+}
+
 pub fn import_builtins(symbol_table: &mut Environment) {
-    symbol_table.insert("print".to_string(), Value::Func { f: lcore_print });
-    symbol_table.insert("prin".to_string(), Value::Func { f: lcore_prin });
-    symbol_table.insert("quit".to_string(), Value::Func { f: lcore_quit });
-    symbol_table.insert("exit".to_string(), Value::Func { f: lcore_quit });
-    symbol_table.insert("set".to_string(), Value::Func { f: lcore_set });
-    symbol_table.insert("loop".to_string(), Value::Func { f: lcore_loop });
-    symbol_table.insert("defn".to_string(), Value::Func { f: lcore_defn });
-    symbol_table.insert("get".to_string(), Value::Func { f: lcore_get });
-    symbol_table.insert("dict".to_string(), Value::Func { f: lcore_dict });
-    symbol_table.insert("len".to_string(), Value::Func { f: lcore_len });
-    symbol_table
-        .insert(String::from("import"), Value::Func { f: lcore_import });
-    symbol_table.insert(String::from("swap"), Value::Func { f: lcore_swap });
-
-    symbol_table.insert("to-str".to_string(), Value::Func { f: lcore_to_str });
-    symbol_table.insert("=".to_string(), Value::Func { f: lcore_equals });
-    symbol_table.insert(
-        "!=".to_string(),
-        Value::Func {
-            f: lcore_not_equals,
-        },
-    );
-    symbol_table.insert(
-        "or".to_string(),
-        Value::Func {
-            f: lcore_logical_or,
-        },
-    );
-    symbol_table.insert(
-        "and".to_string(),
-        Value::Func {
-            f: lcore_logical_and,
-        },
-    );
-    symbol_table.insert(
-        "not".to_string(),
-        Value::Func {
-            f: lcore_logical_not,
-        },
-    );
-    symbol_table.insert("+".to_string(), Value::Func { f: lcore_add });
-    symbol_table.insert("-".to_string(), Value::Func { f: lcore_sub });
-    symbol_table.insert("*".to_string(), Value::Func { f: lcore_mul });
-    symbol_table.insert("/".to_string(), Value::Func { f: lcore_div });
-    symbol_table.insert("**".to_string(), Value::Func { f: lcore_exponent });
-    symbol_table.insert("if".to_string(), Value::Func { f: lcore_if });
-    symbol_table.insert("sel".to_string(), Value::Func { f: lcore_sel });
-    symbol_table.insert("ret".to_string(), Value::Func { f: lcore_return });
-    symbol_table.insert("break".to_string(), Value::Func { f: lcore_break });
-    symbol_table.insert("<".to_string(), Value::Func { f: lcore_less_than });
+    lcore_define_builtin(symbol_table, "print", lcore_print);
+    lcore_define_builtin(symbol_table, "prin", lcore_prin);
+    lcore_define_builtin(symbol_table, "input", lcore_input);
+    lcore_define_builtin(symbol_table, "quit", lcore_quit);
+    lcore_define_builtin(symbol_table, "exit", lcore_quit);
+    lcore_define_builtin(symbol_table, "set", lcore_set);
+    lcore_define_builtin(symbol_table, "loop", lcore_loop);
+    lcore_define_builtin(symbol_table, "defn", lcore_defn);
+    lcore_define_builtin(symbol_table, "fn", lcore_fn);
+    lcore_define_builtin(symbol_table, "lambda", lcore_fn);
+    lcore_define_builtin(symbol_table, "type", lcore_type);
+    lcore_define_builtin(symbol_table, "raise", lcore_raise);
+    lcore_define_builtin(symbol_table, "try", lcore_try);
+    let error_type = Value::Type(symbol_table.error_type.clone());
+    symbol_table.insert("error", error_type);
+    lcore_define_builtin(symbol_table, "new", lcore_new);
+    lcore_define_builtin(symbol_table, "defmacro", lcore_defmacro);
+    lcore_define_builtin(symbol_table, "macroexpand", lcore_macroexpand);
+    lcore_define_builtin(symbol_table, "get", lcore_get);
+    lcore_define_builtin(symbol_table, "dict", lcore_dict);
+    lcore_define_builtin(symbol_table, "len", lcore_len);
+    lcore_define_builtin(symbol_table, "import", lcore_import);
+    lcore_define_builtin(symbol_table, "swap", lcore_swap);
+    lcore_define_builtin(symbol_table, "assoc", lcore_assoc);
+    lcore_define_builtin(symbol_table, "del", lcore_del);
+    lcore_define_builtin(symbol_table, "has?", lcore_has);
+    lcore_define_builtin(symbol_table, "keys", lcore_keys);
+    lcore_define_builtin(symbol_table, "values", lcore_values);
+    lcore_define_builtin(symbol_table, "items", lcore_items);
+    lcore_define_builtin(symbol_table, "merge", lcore_merge);
+    lcore_define_builtin(symbol_table, "push", lcore_push);
+    lcore_define_builtin(symbol_table, "push!", lcore_push_in_place);
+    lcore_define_builtin(symbol_table, "pop", lcore_pop);
+    lcore_define_builtin(symbol_table, "pop!", lcore_pop_in_place);
+    lcore_define_builtin(symbol_table, "insert", lcore_insert);
+    lcore_define_builtin(symbol_table, "insert!", lcore_insert_in_place);
+    lcore_define_builtin(symbol_table, "remove", lcore_remove);
+    lcore_define_builtin(symbol_table, "remove!", lcore_remove_in_place);
+    lcore_define_builtin(symbol_table, "reverse", lcore_reverse);
+    lcore_define_builtin(symbol_table, "reverse!", lcore_reverse_in_place);
+    lcore_define_builtin(symbol_table, "slice", lcore_slice);
+    lcore_define_builtin(symbol_table, "index-of", lcore_index_of);
+    lcore_define_builtin(symbol_table, "contains?", lcore_contains);
+    lcore_define_builtin(symbol_table, "first", lcore_first);
+    lcore_define_builtin(symbol_table, "rest", lcore_rest);
+    lcore_define_builtin(symbol_table, "last", lcore_last);
+    lcore_define_builtin(symbol_table, "flatten", lcore_flatten);
+    lcore_define_builtin(symbol_table, "zip", lcore_zip);
+    lcore_define_builtin(symbol_table, "apply", lcore_apply);
+    lcore_define_builtin(symbol_table, "map", lcore_map);
+    lcore_define_builtin(symbol_table, "filter", lcore_filter);
+    lcore_define_builtin(symbol_table, "reduce", lcore_reduce);
+    lcore_define_builtin(symbol_table, "any?", lcore_any);
+    lcore_define_builtin(symbol_table, "all?", lcore_all);
+    lcore_define_builtin(symbol_table, "find", lcore_find);
+    lcore_define_builtin(symbol_table, "sort", lcore_sort);
+    lcore_define_builtin(symbol_table, "sort-by", lcore_sort_by);
+
+    lcore_define_builtin(symbol_table, "to-str", lcore_to_str);
+    lcore_define_builtin(symbol_table, "=", lcore_equals);
+    lcore_define_builtin(symbol_table, "!=", lcore_not_equals);
+    lcore_define_builtin(symbol_table, "or", lcore_logical_or);
+    lcore_define_builtin(symbol_table, "and", lcore_logical_and);
+    lcore_define_builtin(symbol_table, "not", lcore_logical_not);
+    lcore_define_builtin(symbol_table, "+", lcore_add);
+    lcore_define_builtin(symbol_table, "-", lcore_sub);
+    lcore_define_builtin(symbol_table, "*", lcore_mul);
+    lcore_define_builtin(symbol_table, "/", lcore_div);
+    lcore_define_builtin(symbol_table, "**", lcore_exponent);
+    lcore_define_builtin(symbol_table, "if", lcore_if);
+    lcore_define_builtin(symbol_table, "sel", lcore_sel);
+    lcore_define_builtin(symbol_table, "ret", lcore_return);
+    lcore_define_builtin(symbol_table, "break", lcore_break);
+    lcore_define_builtin(symbol_table, "<", lcore_less_than);
 }
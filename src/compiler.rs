@@ -0,0 +1,670 @@
+//! Turns parsed code into bytecode for `vm` to run.
+//!
+//! The common forms (`set`, `if`, `sel`, `loop`, `ret`, `fn` and `defn`) are
+//! compiled into jumps and stores rather than calls when their blocks are
+//! written out literally, and variables set directly in a function or loop
+//! are given a slot in its scope. A name that has a slot in the function or
+//! loop it is used in, or in any that it is nested in, is found by how many
+//! scopes out it is and its slot rather than by a search. Anything else, such
+//! as a block stored in a variable, is still passed to the builtin of the same
+//! name.
+
+use crate::ast::*;
+use crate::lcore::*;
+use crate::symbol::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::slice;
+
+/// One instruction for the VM, which works on a stack of values.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    /// Push a constant.
+    Const(usize),
+    Int(i64),
+    Null,
+    Pop,
+
+    /// Discard the given number of values.
+    Drop(usize),
+
+    /// Push the variable with the given name, searching every scope.
+    GetName(usize),
+
+    /// Push the variable in a slot of the scope `depth` levels out from the
+    /// innermost one.
+    GetLocal {
+        depth: usize,
+        slot: usize,
+    },
+
+    /// Pop a value and `set` the variable with the given name, pushing
+    /// `Null`.
+    SetName(usize),
+
+    /// Pop a value and `set` the variable in a slot of the scope `depth`
+    /// levels out from the innermost one, pushing `Null`.
+    SetLocal {
+        depth: usize,
+        slot: usize,
+    },
+
+    /// If the function about to be called is a macro, expand it with the
+    /// unevaluated arguments in the given entry of `Chunk::arguments` and
+    /// jump to `end`.
+    Macro {
+        args: usize,
+        end: usize,
+    },
+
+    /// Call the function below the given number of arguments.
+    Call(usize),
+
+    /// Like `Call` but in tail position, so that a user-defined function can
+    /// reuse the frame of the function making the call.
+    TailCall(usize),
+
+    /// Collect the given number of values into an array.
+    Array(usize),
+
+    /// Push a quasi-quoted template from `Chunk::templates` with its
+    /// unquotes filled in.
+    QuasiQuote(usize),
+
+    Jump(usize),
+
+    /// Pop the condition of an `if` and jump if it is `False`.
+    Branch(usize),
+
+    /// Compare the value being selected on by `sel` with one of the `count`
+    /// values above it, jumping to `next` if they do not match.
+    SelTest {
+        value: usize,
+        count: usize,
+        next: usize,
+    },
+
+    /// Pop the number of iterations of a `loop` and enter a scope laid out
+    /// by the given entry of `Chunk::scopes`.
+    EnterLoop {
+        scope: usize,
+        exit: usize,
+    },
+
+    /// Set the loop variable for the next iteration or jump to `exit` once
+    /// there are none left.
+    NextLoop(usize),
+
+    /// Leave the scope of the innermost loop, pushing `Null`.
+    ExitLoop,
+
+    /// Push a function from `Chunk::functions` that captures the current
+    /// scope.
+    Lambda(usize),
+
+    /// Return the top of the stack from the function being run.
+    Return,
+}
+
+/// Compiled code along with the values and names it refers to.
+#[derive(Default)]
+pub struct Chunk {
+    pub ops: Vec<Op>,
+
+    /// The innermost call the ops belong to from each point on, for
+    /// tracebacks.
+    pub spans: Vec<(usize, Option<Span>)>,
+
+    pub constants: Vec<Value>,
+    pub names: Vec<Symbol>,
+
+    /// The variables kept in slots by each loop.
+    pub scopes: Vec<Rc<[Symbol]>>,
+
+    /// The functions created by `fn` and `defn`.
+    pub functions: Vec<Rc<Function>>,
+
+    /// The quasi-quoted templates in the code.
+    pub templates: Vec<Rc<Expr>>,
+
+    /// The function and unevaluated arguments of each call along with where
+    /// it was written, in case the function turns out to be a macro.
+    pub arguments: Vec<(Span, Rc<[Expr]>)>,
+}
+
+impl Chunk {
+    /// The innermost call an op belongs to.
+    pub fn span(&self, ip: usize) -> Option<Span> {
+        let after = self.spans.partition_point(|&(at, _)| at <= ip);
+        match after {
+            0 => None,
+            _ => self.spans[after - 1].1.clone(),
+        }
+    }
+}
+
+/// The compiled body of a user-defined function or macro.
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<Symbol>,
+
+    /// The slot each parameter is bound to.
+    pub param_slots: Vec<usize>,
+
+    /// The variables kept in slots by each call, parameters first.
+    pub slots: Rc<[Symbol]>,
+
+    pub chunk: Chunk,
+}
+
+/// Compile a block of code to run in whichever scope it is called from.
+///
+/// When `tail` is set, a call that is the last thing in the block becomes a
+/// tail call.
+pub fn lcore_compile_block(forms: &[Expr], tail: bool) -> Chunk {
+    let mut compiler = Compiler::new(vec![None], false);
+    compiler.forms(forms, tail);
+    compiler.chunk
+}
+
+/// Compile the body of a user-defined function.
+pub fn lcore_compile_function(
+    name: Option<String>,
+    params: Vec<Symbol>,
+    body: &[Expr],
+) -> Function {
+    lcore_compile_nested_function(name, params, body, Vec::new())
+}
+
+/// Compile the body of a function given the slots of the scopes it will be
+/// defined in, innermost last.
+fn lcore_compile_nested_function(
+    name: Option<String>,
+    params: Vec<Symbol>,
+    body: &[Expr],
+    mut scopes: Vec<Option<Vec<Symbol>>>,
+) -> Function {
+    scopes.push(Some(Vec::new()));
+    let mut compiler = Compiler::new(scopes, true);
+    let param_slots = params.iter().map(|&p| compiler.slot(p)).collect();
+    compiler.forms(body, true);
+
+    let slots = compiler.scopes.pop().unwrap().unwrap();
+
+    Function {
+        name,
+        params,
+        param_slots,
+        slots: Rc::from(slots),
+        chunk: compiler.chunk,
+    }
+}
+
+struct Compiler {
+    chunk: Chunk,
+    name_index: HashMap<Symbol, usize>,
+
+    /// The slots of each scope being compiled, innermost last. The scope a
+    /// block is run in is not known until then, so it has none, and neither
+    /// do any outside of it.
+    scopes: Vec<Option<Vec<Symbol>>>,
+
+    /// Whether `ret` can return from the chunk itself.
+    function: bool,
+
+    /// The call being compiled.
+    span: Option<Span>,
+}
+
+impl Compiler {
+    fn new(scopes: Vec<Option<Vec<Symbol>>>, function: bool) -> Compiler {
+        Compiler {
+            chunk: Chunk::default(),
+            name_index: HashMap::new(),
+            scopes,
+            function,
+            span: None,
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.ops.push(op);
+        self.chunk.ops.len() - 1
+    }
+
+    /// Note that the ops from here on belong to the current call.
+    fn mark(&mut self) {
+        let at = self.chunk.ops.len();
+        match self.chunk.spans.last_mut() {
+            Some(last) if last.0 == at => last.1 = self.span.clone(),
+            _ => self.chunk.spans.push((at, self.span.clone())),
+        }
+    }
+
+    /// Point a jump emitted earlier at the next op.
+    fn patch(&mut self, at: usize) {
+        let target = self.chunk.ops.len();
+        match &mut self.chunk.ops[at] {
+            Op::Macro { end, .. } => *end = target,
+            Op::Jump(to) | Op::Branch(to) | Op::NextLoop(to) => *to = target,
+            Op::SelTest { next, .. } => *next = target,
+            Op::EnterLoop { exit, .. } => *exit = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> usize {
+        self.chunk.constants.push(value);
+        self.chunk.constants.len() - 1
+    }
+
+    fn name(&mut self, name: Symbol) -> usize {
+        if let Some(&index) = self.name_index.get(&name) {
+            return index;
+        }
+
+        self.chunk.names.push(name);
+        let index = self.chunk.names.len() - 1;
+        self.name_index.insert(name, index);
+        index
+    }
+
+    /// The slot of a variable in the innermost scope, making one if needed.
+    fn slot(&mut self, name: Symbol) -> usize {
+        let slots = self.scopes.last_mut().unwrap().as_mut().unwrap();
+        match slots.iter().position(|&s| s == name) {
+            Some(slot) => slot,
+            None => {
+                slots.push(name);
+                slots.len() - 1
+            }
+        }
+    }
+
+    /// How many scopes out a variable's slot is and which slot it is, if it
+    /// has one in any of the scopes that are known.
+    fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            let slots = scope.as_ref()?;
+            if let Some(slot) = slots.iter().position(|&s| s == name) {
+                return Some((depth, slot));
+            }
+        }
+        None
+    }
+
+    fn get(&mut self, name: Symbol) {
+        match self.resolve(name) {
+            Some((depth, slot)) => self.emit(Op::GetLocal { depth, slot }),
+            None => {
+                let name = self.name(name);
+                self.emit(Op::GetName(name))
+            }
+        };
+    }
+
+    fn set(&mut self, name: Symbol) {
+        if let Some((depth, slot)) = self.resolve(name) {
+            self.emit(Op::SetLocal { depth, slot });
+        } else if let Some(Some(_)) = self.scopes.last() {
+            let slot = self.slot(name);
+            self.emit(Op::SetLocal { depth: 0, slot });
+        } else {
+            let name = self.name(name);
+            self.emit(Op::SetName(name));
+        }
+    }
+
+    /// Compile a sequence of forms, leaving the value of the last one.
+    fn forms(&mut self, forms: &[Expr], tail: bool) {
+        if forms.is_empty() {
+            self.emit(Op::Null);
+        }
+
+        for (i, form) in forms.iter().enumerate() {
+            if i > 0 {
+                self.emit(Op::Pop);
+            }
+            self.form(form, tail && i == forms.len() - 1);
+        }
+    }
+
+    /// Compile the code in a quoted block such as `'[...]`.
+    fn block(&mut self, block: &Expr, tail: bool) {
+        match block {
+            Expr::Quote(_, q) => match &**q {
+                Expr::Array(_, forms) => self.forms(forms, tail),
+                other => self.forms(slice::from_ref(other), tail),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn form(&mut self, form: &Expr, tail: bool) {
+        match form {
+            Expr::Call(span, items) => self.call(span, items, tail),
+
+            Expr::QuasiQuote(_, template) => {
+                self.chunk.templates.push(template.clone());
+                self.emit(Op::QuasiQuote(self.chunk.templates.len() - 1));
+            }
+
+            Expr::Symbol(_, name) => self.get(*name),
+
+            Expr::Array(_, items) if !lcore_is_constant(items) => {
+                for item in items {
+                    self.form(item, false);
+                }
+                self.emit(Op::Array(items.len()));
+            }
+
+            // NOTE(pebaz): Only code made by a macro can have an unquote
+            // outside of a template, which just stands for its form.
+            Expr::Unquote(_, form) | Expr::Splice(_, form) => {
+                self.form(form, tail)
+            }
+
+            Expr::Literal(_, Value::Int(i)) => {
+                self.emit(Op::Int(*i));
+            }
+
+            Expr::Literal(_, Value::Null) => {
+                self.emit(Op::Null);
+            }
+
+            form => {
+                let value = self.constant(lcore_expr_value(form));
+                self.emit(Op::Const(value));
+            }
+        }
+    }
+
+    fn call(&mut self, span: &Span, items: &Rc<[Expr]>, tail: bool) {
+        let outer = self.span.replace(span.clone());
+        self.mark();
+
+        let special = match items.first() {
+            Some(&Expr::Symbol(_, name)) if self.resolve(name).is_none() => {
+                self.special(&name.name(), &items[1..], tail)
+            }
+            _ => false,
+        };
+
+        if !special {
+            self.generic_call(span, items, tail);
+        }
+
+        self.span = outer;
+        self.mark();
+    }
+
+    /// Compile a call to a function that is looked up at run time.
+    fn generic_call(&mut self, span: &Span, items: &Rc<[Expr]>, tail: bool) {
+        let check = match items.first() {
+            Some(&Expr::Symbol(_, name)) => {
+                self.get(name);
+
+                // NOTE(pebaz): Whether the function is a macro is only known
+                // once it has been looked up.
+                self.chunk.arguments.push((span.clone(), items.clone()));
+                let check = self.emit(Op::Macro {
+                    args: self.chunk.arguments.len() - 1,
+                    end: 0,
+                });
+                Some(check)
+            }
+
+            Some(callee) => {
+                self.form(callee, false);
+                None
+            }
+
+            None => {
+                self.emit(Op::Null);
+                None
+            }
+        };
+
+        let args = items.get(1..).unwrap_or(&[]);
+        for arg in args {
+            self.form(arg, false);
+        }
+
+        if tail {
+            self.emit(Op::TailCall(args.len()));
+        } else {
+            self.emit(Op::Call(args.len()));
+        }
+
+        if let Some(check) = check {
+            self.patch(check);
+        }
+    }
+
+    /// Compile a call to one of the common builtins in place.
+    ///
+    /// Returns: Whether the call could be compiled this way. If not, nothing
+    /// has been emitted.
+    fn special(&mut self, name: &str, args: &[Expr], tail: bool) -> bool {
+        match (name, args) {
+            ("set", [var, value]) => match lcore_quoted_name(var) {
+                Some(var) => {
+                    self.form(value, false);
+                    self.set(var);
+                    true
+                }
+                None => false,
+            },
+
+            ("if", [condition, then]) if lcore_is_block(then) => {
+                self.if_(condition, then, None, tail);
+                true
+            }
+
+            ("if", [condition, then, otherwise])
+                if lcore_is_block(then) && lcore_is_block(otherwise) =>
+            {
+                self.if_(condition, then, Some(otherwise), tail);
+                true
+            }
+
+            ("sel", [_, rest @ ..])
+                if rest.chunks(2).all(|pair| match pair {
+                    [_, code] => lcore_is_block(code),
+                    _ => true,
+                }) =>
+            {
+                self.sel(args, tail);
+                true
+            }
+
+            ("loop", [var, iters, body]) if lcore_is_block(body) => {
+                match lcore_quoted_name(var) {
+                    Some(var) => {
+                        self.loop_(var, iters, body);
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            ("ret", []) if self.function => {
+                self.emit(Op::Null);
+                self.emit(Op::Return);
+                true
+            }
+
+            ("ret", [value]) if self.function => {
+                // NOTE(pebaz): Returning the value of a call is a tail call
+                self.form(value, true);
+                self.emit(Op::Return);
+                true
+            }
+
+            ("fn", [params, body]) | ("lambda", [params, body]) => {
+                self.lambda(None, params, body)
+            }
+
+            ("defn", [var, params, body]) => match lcore_quoted_name(var) {
+                Some(var) => {
+                    let compiled = self.lambda(
+                        Some(var.name().to_string()),
+                        params,
+                        body,
+                    );
+                    if compiled {
+                        self.set(var);
+                    }
+                    compiled
+                }
+                None => false,
+            },
+
+            _ => false,
+        }
+    }
+
+    fn if_(
+        &mut self,
+        condition: &Expr,
+        then: &Expr,
+        otherwise: Option<&Expr>,
+        tail: bool,
+    ) {
+        self.form(condition, false);
+        let branch = self.emit(Op::Branch(0));
+        self.block(then, tail);
+        let jump = self.emit(Op::Jump(0));
+        self.patch(branch);
+
+        match otherwise {
+            Some(otherwise) => self.block(otherwise, tail),
+            None => {
+                self.emit(Op::Null);
+            }
+        }
+        self.patch(jump);
+    }
+
+    /// Every value is evaluated before any of them is compared, just like
+    /// the arguments of any other call.
+    fn sel(&mut self, args: &[Expr], tail: bool) {
+        let count = args[1..].iter().step_by(2).count();
+
+        self.form(&args[0], false);
+        for value in args[1..].iter().step_by(2) {
+            self.form(value, false);
+        }
+
+        let mut ends = Vec::new();
+        for (i, pair) in args[1..].chunks(2).enumerate() {
+            let code = match pair {
+                [_, code] => code,
+                _ => break,
+            };
+
+            let test = self.emit(Op::SelTest {
+                value: i,
+                count,
+                next: 0,
+            });
+            self.emit(Op::Drop(count + 1));
+            self.block(code, tail);
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(test);
+        }
+
+        self.emit(Op::Drop(count + 1));
+        self.emit(Op::Null);
+
+        for end in ends {
+            self.patch(end);
+        }
+    }
+
+    fn loop_(&mut self, var: Symbol, iters: &Expr, body: &Expr) {
+        self.form(iters, false);
+
+        let scope = self.chunk.scopes.len();
+        self.chunk.scopes.push(Rc::from(Vec::new()));
+        let enter = self.emit(Op::EnterLoop { scope, exit: 0 });
+
+        self.scopes.push(Some(vec![var]));
+        let next = self.emit(Op::NextLoop(0));
+        self.block(body, false);
+        self.emit(Op::Pop);
+        self.emit(Op::Jump(next));
+        let slots = self.scopes.pop().unwrap().unwrap();
+        self.chunk.scopes[scope] = Rc::from(slots);
+
+        self.patch(next);
+        self.patch(enter);
+        self.emit(Op::ExitLoop);
+    }
+
+    /// Compile a function whose parameters and body are written out.
+    fn lambda(
+        &mut self,
+        name: Option<String>,
+        params: &Expr,
+        body: &Expr,
+    ) -> bool {
+        let params = match params {
+            Expr::Array(_, params) => params
+                .iter()
+                .map(lcore_quoted_name)
+                .collect::<Option<Vec<Symbol>>>(),
+            _ => None,
+        };
+
+        let body = match body {
+            Expr::Quote(_, q) => match &**q {
+                Expr::Array(_, body) => Some(body),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let (params, body) = match (params, body) {
+            (Some(params), Some(body)) => (params, body),
+            _ => return false,
+        };
+
+        // NOTE(pebaz): The function is nested in the scope it is created in,
+        // so it can find the variables of any scope around it by slot too.
+        let function = lcore_compile_nested_function(
+            name,
+            params,
+            body,
+            self.scopes.clone(),
+        );
+        self.chunk.functions.push(Rc::new(function));
+        self.emit(Op::Lambda(self.chunk.functions.len() - 1));
+        true
+    }
+}
+
+/// Check if a form is a quoted block such as `'[...]`.
+fn lcore_is_block(form: &Expr) -> bool {
+    matches!(form, Expr::Quote(..))
+}
+
+/// Check if an array literal contains nothing that needs to be evaluated.
+fn lcore_is_constant(items: &[Expr]) -> bool {
+    items.iter().all(|item| match item {
+        Expr::Literal(..) | Expr::Quote(..) => true,
+        Expr::Array(_, items) => lcore_is_constant(items),
+        _ => false,
+    })
+}
+
+/// The name in a quoted identifier such as `'x`.
+fn lcore_quoted_name(form: &Expr) -> Option<Symbol> {
+    match form {
+        Expr::Quote(_, q) => match &**q {
+            &Expr::Symbol(_, name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
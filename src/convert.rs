@@ -0,0 +1,373 @@
+use crate::lcore::*;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::rc::Rc;
+
+/// A Rust value that can be turned into a LambdaCore value.
+///
+/// Structs with named fields can derive this to become a dict keyed by the
+/// field names.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// A Rust value that can be read back out of a LambdaCore value.
+///
+/// Structs with named fields can derive this to be read from a dict keyed by
+/// the field names or from a LambdaCore struct with the same fields.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, LCoreError>;
+}
+
+/// The error for a value that has the wrong type to be converted.
+fn lcore_convert_error(value: &Value, expected: &str) -> LCoreError {
+    LCoreError::TypeError(format!(
+        "Cannot convert {:?} to {}",
+        value, expected
+    ))
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Null
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Null => Ok(()),
+            other => Err(lcore_convert_error(&other, "Null")),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(lcore_convert_error(&other, "a Boolean")),
+        }
+    }
+}
+
+// NOTE(pebaz): Ints are stored as i64, so only integer types that always fit
+// can be converted into one. Reading any of them back checks the range.
+macro_rules! lcore_int_conversions {
+    ($($int:ty),*) => {
+        $(
+            impl FromValue for $int {
+                fn from_value(value: Value) -> Result<Self, LCoreError> {
+                    match value {
+                        Value::Int(i) => <$int>::try_from(i).map_err(|_| {
+                            LCoreError::ArgumentError(format!(
+                                "Int {} is out of range for {}",
+                                i,
+                                stringify!($int)
+                            ))
+                        }),
+                        other => Err(lcore_convert_error(&other, "an Int")),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+lcore_int_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! lcore_into_int {
+    ($($int:ty),*) => {
+        $(
+            impl IntoValue for $int {
+                fn into_value(self) -> Value {
+                    Value::Int(i64::from(self))
+                }
+            }
+        )*
+    };
+}
+
+lcore_into_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Float(f) => Ok(f),
+            Value::Int(i) => Ok(i as f64),
+            other => Err(lcore_convert_error(&other, "a Float")),
+        }
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Float(f64::from(self))
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        f64::from_value(value).map(|f| f as f32)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(Rc::new(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(Rc::new(self.to_string()))
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::String(s) => Ok(Rc::unwrap_or_clone(s)),
+            other => Err(lcore_convert_error(&other, "a String")),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let items = self.into_iter().map(IntoValue::into_value).collect();
+        Value::Array(Rc::new(items))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Array(items) => Rc::unwrap_or_clone(items)
+                .into_iter()
+                .map(T::from_value)
+                .collect(),
+            other => Err(lcore_convert_error(&other, "an Array")),
+        }
+    }
+}
+
+impl<K: IntoValue, V: IntoValue> IntoValue for HashMap<K, V> {
+    fn into_value(self) -> Value {
+        Value::Dict(Rc::new(
+            self.into_iter()
+                .map(|(k, v)| (k.into_value(), v.into_value()))
+                .collect(),
+        ))
+    }
+}
+
+impl<K, V> FromValue for HashMap<K, V>
+where
+    K: FromValue + Eq + Hash,
+    V: FromValue,
+{
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Dict(dict) => Rc::unwrap_or_clone(dict)
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            other => Err(lcore_convert_error(&other, "a Dict")),
+        }
+    }
+}
+
+// NOTE(pebaz): An IndexMap keeps its order both ways, whereas a HashMap has
+// none to keep.
+impl<K: IntoValue, V: IntoValue> IntoValue for IndexMap<K, V> {
+    fn into_value(self) -> Value {
+        Value::Dict(Rc::new(
+            self.into_iter()
+                .map(|(k, v)| (k.into_value(), v.into_value()))
+                .collect(),
+        ))
+    }
+}
+
+impl<K, V> FromValue for IndexMap<K, V>
+where
+    K: FromValue + Eq + Hash,
+    V: FromValue,
+{
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Dict(dict) => Rc::unwrap_or_clone(dict)
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            other => Err(lcore_convert_error(&other, "a Dict")),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, LCoreError> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+// NOTE(pebaz): Tuples are read from arrays so that the arguments of a native
+// function can be converted all at once.
+macro_rules! lcore_tuple_conversions {
+    ($len:expr => $($name:ident),*) => {
+        impl<$($name: IntoValue),*> IntoValue for ($($name,)*) {
+            fn into_value(self) -> Value {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                Value::Array(Rc::new(vec![$($name.into_value()),*]))
+            }
+        }
+
+        impl<$($name: FromValue),*> FromValue for ($($name,)*) {
+            fn from_value(value: Value) -> Result<Self, LCoreError> {
+                match value {
+                    Value::Array(items) if items.len() == $len => {
+                        let mut items = Rc::unwrap_or_clone(items).into_iter();
+                        Ok(($($name::from_value(items.next().unwrap())?,)*))
+                    }
+                    other => Err(lcore_convert_error(
+                        &other,
+                        concat!("an Array of ", $len, " values"),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+lcore_tuple_conversions!(1 => A);
+lcore_tuple_conversions!(2 => A, B);
+lcore_tuple_conversions!(3 => A, B, C);
+lcore_tuple_conversions!(4 => A, B, C, D);
+
+/// Create an instance of a LambdaCore struct type from a Rust value that
+/// converts to a dict with a key for each of its fields.
+pub fn lcore_into_struct(
+    value: impl IntoValue,
+    kind: &Rc<StructType>,
+) -> Result<Value, LCoreError> {
+    let mut fields = Fields::new(value.into_value(), &kind.name)?;
+
+    let values = kind
+        .fields
+        .iter()
+        .map(|field| fields.take(field))
+        .collect::<Result<Vec<Value>, LCoreError>>()?;
+
+    Ok(Value::Struct {
+        kind: kind.clone(),
+        fields: values,
+    })
+}
+
+/// Create a dict with a String key for each field of a Rust struct, in the
+/// order the fields are declared.
+///
+/// Used by the `IntoValue` derive.
+pub fn lcore_dict_from_fields(fields: Vec<(&str, Value)>) -> Value {
+    Value::Dict(Rc::new(
+        fields
+            .into_iter()
+            .map(|(name, value)| {
+                (Value::String(Rc::new(name.to_string())), value)
+            })
+            .collect(),
+    ))
+}
+
+/// The named fields of a dict or struct being converted into a Rust struct.
+///
+/// Used by the `FromValue` derive.
+pub struct Fields {
+    type_name: String,
+    values: HashMap<String, Value>,
+}
+
+impl Fields {
+    pub fn new(value: Value, type_name: &str) -> Result<Fields, LCoreError> {
+        let values = match value {
+            Value::Dict(dict) => Rc::unwrap_or_clone(dict)
+                .into_iter()
+                .filter_map(|(key, value)| match key {
+                    Value::String(key) => {
+                        Some((Rc::unwrap_or_clone(key), value))
+                    }
+                    Value::Identifier(key) => Some((key, value)),
+                    _ => None,
+                })
+                .collect(),
+            Value::Struct { kind, fields } => {
+                kind.fields.iter().cloned().zip(fields).collect()
+            }
+            other => {
+                return Err(lcore_convert_error(
+                    &other,
+                    &format!("a Dict or Struct for {}", type_name),
+                ))
+            }
+        };
+
+        Ok(Fields {
+            type_name: type_name.to_string(),
+            values,
+        })
+    }
+
+    /// Convert the field named `name`. A missing field is read as `Null` so
+    /// that optional fields can be left out.
+    pub fn take<T: FromValue>(&mut self, name: &str) -> Result<T, LCoreError> {
+        match self.values.remove(name) {
+            Some(value) => T::from_value(value),
+            None => T::from_value(Value::Null).map_err(|_| {
+                LCoreError::KeyError(format!(
+                    "Missing field \"{}\" for {}",
+                    name, self.type_name
+                ))
+            }),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::builtin::*;
+use crate::lcore::*;
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Runs LambdaCore code on behalf of a Rust program.
+///
+/// Globals defined by one call are visible to the next, so a program can be
+/// loaded with `eval_file` and then driven with `call_function`.
+///
+/// It can be used from any thread: recursion deeper than `LCORE_MAX_DEPTH`
+/// fails with an error rather than overflowing the stack.
+pub struct Interpreter {
+    symbol_table: Environment,
+}
+
+impl Interpreter {
+    /// Create an interpreter with only the builtins defined.
+    pub fn new() -> Interpreter {
+        let mut symbol_table = Environment::new();
+        import_builtins(&mut symbol_table);
+
+        Interpreter { symbol_table }
+    }
+
+    /// Run a string of code.
+    ///
+    /// Returns: The value of the last form in the code.
+    pub fn eval_str(&mut self, code: &str) -> Result<Value, LCoreError> {
+        self.forget_traceback();
+        lcore_eval(code, "<string>", &mut self.symbol_table)
+    }
+
+    /// Run the code in a file.
+    ///
+    /// Returns: The value of the last form in the file.
+    pub fn eval_file(&mut self, file: &str) -> Result<Value, LCoreError> {
+        self.forget_traceback();
+        let code = lcore_read_file(file)?;
+        lcore_eval(&code, file, &mut self.symbol_table)
+    }
+
+    /// Send the output of `print` and `prin` somewhere other than standard
+    /// output, such as a shared `Vec<u8>` to capture it.
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        self.symbol_table.output = output;
+    }
+
+    /// Read the lines for `input` from somewhere other than standard input.
+    pub fn set_input(&mut self, input: Rc<RefCell<dyn BufRead>>) {
+        self.symbol_table.input = input;
+    }
+
+    /// Get the value of a global variable.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        // NOTE(pebaz): Don't intern names that were never defined
+        Symbol::lookup(name).and_then(|name| self.symbol_table.get(name))
+    }
+
+    /// Set a global variable, defining it if it does not exist yet.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.symbol_table.define(name, value);
+    }
+
+    /// Make a function written in Rust callable from scripts under its name.
+    ///
+    /// The function can capture state from the embedding program, like a
+    /// counter in an `Rc<Cell<i64>>`.
+    pub fn register_function(&mut self, func: NativeFunc) {
+        let name = func.name().to_string();
+        self.set_global(&name, Value::Func(Rc::new(func)));
+    }
+
+    /// Call the function stored in a global variable with `args`.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, LCoreError> {
+        self.forget_traceback();
+        match self.get_global(name) {
+            Some(func) => lcore_call(
+                func,
+                Value::Array(Rc::new(args)),
+                &mut self.symbol_table,
+            ),
+            None => {
+                LCoreError::Name(format!("Cannot lookup name: \"{}\"", name))
+            }
+        }
+    }
+
+    /// Print an error along with the calls that led to it.
+    ///
+    /// Errors are returned rather than printed, so this is for programs that
+    /// want to show them the same way the `lambda_core` binary does.
+    pub fn report_error(&mut self, err: &LCoreError) {
+        lcore_report_error(err, &mut self.symbol_table);
+    }
+
+    /// Describe an error along with the calls that led to it, as
+    /// `report_error` would print it.
+    pub fn format_error(&mut self, err: &LCoreError) -> String {
+        lcore_format_error(err, &mut self.symbol_table)
+    }
+
+    /// Drop the traceback of an earlier error that was never reported so it
+    /// is not mixed up with the next one.
+    fn forget_traceback(&mut self) {
+        self.symbol_table.take_traceback();
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
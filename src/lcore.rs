@@ -2,28 +2,55 @@
 pub extern crate pest;
 extern crate pest_derive;
 
+use crate::ast::*;
 use crate::builtin::*;
-use pest::error::Error;
-use pest::error::ErrorVariant::ParsingError;
-use pest::iterators::Pair;
+use crate::compiler::*;
+use crate::symbol::*;
+use crate::vm::*;
+use indexmap::IndexMap;
+use pest::error::ErrorVariant::{CustomError, ParsingError};
+use pest::error::{Error, LineColLocation};
+use pest::iterators::{Pair, Pairs};
 use pest::Parser;
+use std::cell::RefCell;
 use std::cmp::{Eq, PartialEq};
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::hash::{Hash, Hasher};
-use std::io::{self, Write};
-use std::iter::FromIterator;
+use std::io::{self, BufRead, Write};
+use std::mem;
 use std::process::exit;
+use std::rc::{Rc, Weak};
+use std::slice;
 use std::str::FromStr;
 
 #[derive(pest_derive::Parser)]
 #[grammar = "LambdaCore.pest"]
 pub struct LambdaCoreParser;
 
-type SymTab = HashMap<String, Value>;
+type SymTab = SymbolMap<Value>;
 
-static LCORE_DEBUG: bool = false;
+/// The deepest that user-defined functions can call each other before the
+/// call fails instead of overflowing the stack.
+pub static LCORE_MAX_DEPTH: usize = 10_000;
+
+/// How close running code can get to the end of the stack before the next
+/// block of code is run on a new stack segment.
+pub static LCORE_STACK_RED_ZONE: usize = 256 * 1024;
+
+/// The size of each stack segment added for deep recursion.
+pub static LCORE_STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// How many compiled blocks are cached before checking for any that are no
+/// longer used.
+pub static LCORE_BLOCK_CACHE_SIZE: usize = 1024;
+
+/// The status the process exits with when an error is not caught.
+pub static LCORE_EXIT_RUNTIME_ERROR: i32 = 1;
+
+/// The status the process exits with when a program cannot be parsed.
+pub static LCORE_EXIT_PARSE_ERROR: i32 = 2;
 
 #[derive(Clone)]
 pub enum Value {
@@ -32,111 +59,320 @@ pub enum Value {
     Boolean(bool),
     Int(i64),
     Float(f64),
-    String(String),
-    Array(Vec<Value>),
-    Func {
-        f: fn(&mut Value, &mut Environment) -> Result<Value, LCoreError>,
-    },
-    Quote(Box<Value>),
-    Dict(HashMap<Value, Value>),
-
-    // TODO(pebaz):
+    String(Rc<String>),
+    Array(Rc<Vec<Value>>),
+    Func(Rc<NativeFunc>),
+    Quote(Rc<Expr>),
+    Dict(Rc<Dict>),
+    Lambda(Rc<Lambda>),
+    Macro(Rc<Lambda>),
+    Type(Rc<StructType>),
     Struct {
-        name: String,
+        kind: Rc<StructType>,
         fields: Vec<Value>,
     },
+}
+
+/// The entries of a dict, kept in the order their keys were first inserted.
+/// Setting a key that is already there keeps its place, while removing a key
+/// with `shift_remove` keeps the rest in order and a key inserted again goes
+/// last.
+pub type Dict = IndexMap<Value, Value>;
+
+/// A user-defined function created by `fn` or `defn`, or a macro created by
+/// `defmacro`.
+pub struct Lambda {
+    pub name: Option<String>,
+    pub code: Rc<Function>,
+    pub env: Rc<RefCell<Scope>>,
+}
+
+/// The signature of the builtins written in Rust.
+pub type NativeFn =
+    fn(&mut Value, &mut Environment) -> Result<Value, LCoreError>;
+
+type NativeClosure =
+    dyn Fn(&mut Value, &mut Environment) -> Result<Value, LCoreError>;
+
+/// A function written in Rust, either a builtin or one registered by a program
+/// embedding the interpreter. Unlike a plain `NativeFn`, it can capture state.
+pub struct NativeFunc {
+    name: String,
+    arity: Option<(usize, usize)>,
+    f: Box<NativeClosure>,
+}
 
-    // Lexical Values
-    OpenFunc,
-    CloseFunc,
-    OpenBrace,
-    CloseBrace,
-    BackTick,
-    Comma,
+impl NativeFunc {
+    pub fn new(
+        name: &str,
+        f: impl Fn(&mut Value, &mut Environment) -> Result<Value, LCoreError>
+            + 'static,
+    ) -> NativeFunc {
+        NativeFunc {
+            name: name.to_string(),
+            arity: None,
+            f: Box::new(f),
+        }
+    }
+
+    /// Check that calls pass between `min` and `max` arguments before running
+    /// the function. Use `usize::MAX` for no upper limit.
+    pub fn with_arity(mut self, min: usize, max: usize) -> NativeFunc {
+        self.arity = Some((min, max));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> Option<(usize, usize)> {
+        self.arity
+    }
+
+    /// Run the function with an array of already evaluated arguments.
+    pub fn call(
+        &self,
+        args: &mut Value,
+        symbol_table: &mut Environment,
+    ) -> Result<Value, LCoreError> {
+        if let Some((min, max)) = self.arity {
+            lcore_check_arity(args.as_array(), &self.name, min, max)?;
+        }
+        (self.f)(args, symbol_table)
+    }
+}
+
+/// Where a form was written in the source code, as the byte offsets of its
+/// start and end. Its line and column are only worked out when they are
+/// needed, such as for a traceback.
+#[derive(Clone)]
+pub struct Span {
+    source: Rc<Source>,
+    start: usize,
+    end: usize,
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        let (line, col) = self.line_col();
+        write!(fm, "{}:{}:{}", self.file(), line, col)
+    }
+}
+
+impl Span {
+    pub fn file(&self) -> &str {
+        &self.source.file
+    }
+
+    /// The byte offset of the start of the form.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset just past the end of the form.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The line and column of the start of the span, both counting from 1.
+    pub fn line_col(&self) -> (usize, usize) {
+        let starts = &self.source.line_starts;
+        let line = match starts.binary_search(&self.start) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        (line + 1, self.start - starts[line] + 1)
+    }
+}
+
+/// The name of a source file along with where each of its lines start, so
+/// that parsed forms can be given their line and column.
+#[derive(Debug)]
+pub struct Source {
+    file: Rc<str>,
+    line_starts: Vec<usize>,
+}
+
+impl Source {
+    pub fn new(file: &str, code: &str) -> Source {
+        let mut line_starts = vec![0];
+        for (i, c) in code.bytes().enumerate() {
+            if c == b'\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
+        Source {
+            file: Rc::from(file),
+            line_starts,
+        }
+    }
+
+    /// The span from byte offset `start` up to `end`.
+    pub fn span(self: &Rc<Self>, start: usize, end: usize) -> Span {
+        Span {
+            source: self.clone(),
+            start,
+            end,
+        }
+    }
+}
+
+/// One function call in the chain of calls that led to an error.
+#[derive(Debug)]
+pub struct TraceFrame {
+    pub span: Span,
+
+    /// The function the call was made from, or `None` for the top level.
+    pub function: Option<String>,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        let (line, col) = self.span.line_col();
+        write!(
+            fm,
+            "  File \"{}\", line {}, column {}, in {}",
+            self.span.file(),
+            line,
+            col,
+            self.function.as_deref().unwrap_or("<module>")
+        )
+    }
+}
+
+/// A struct type created by `type`. Instances created by `new` keep a
+/// reference to it so that their fields can be looked up by name.
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+impl StructType {
+    pub fn field_index(&self, field: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f == field)
+    }
 }
 
 impl Value {
     pub fn as_identifier(&self) -> &String {
         match self {
-            Value::Identifier(ref i) => return i,
+            Value::Identifier(ref i) => i,
             _ => unreachable!(),
         }
     }
 
     pub fn as_bool(&self) -> &bool {
         match self {
-            Value::Boolean(ref b) => return b,
+            Value::Boolean(ref b) => b,
             _ => unreachable!(),
         }
     }
 
     pub fn as_int(&self) -> &i64 {
         match self {
-            Value::Int(ref i) => return i,
+            Value::Int(ref i) => i,
             _ => unreachable!(),
         }
     }
 
     pub fn as_float(&self) -> &f64 {
         match self {
-            Value::Float(ref f) => return f,
+            Value::Float(ref f) => f,
             _ => unreachable!(),
         }
     }
 
     pub fn as_string(&self) -> &String {
         match self {
-            Value::String(ref s) => return s,
+            Value::String(ref s) => s,
             _ => unreachable!(),
         }
     }
 
     pub fn as_array(&self) -> &Vec<Value> {
         match self {
-            Value::Array(ref a) => return a,
+            Value::Array(ref a) => a,
             _ => unreachable!(),
         }
     }
 
-    pub fn as_dict(&mut self) -> &mut HashMap<Value, Value> {
+    /// Whether the value can be used as a dict key. Dicts and functions
+    /// cannot, nor can an array or struct that holds one.
+    pub fn is_hashable(&self) -> bool {
         match self {
-            Value::Dict(ref mut d) => return d,
+            Value::Dict(_)
+            | Value::Func(_)
+            | Value::Lambda(_)
+            | Value::Macro(_) => false,
+            Value::Array(items) => items.iter().all(Value::is_hashable),
+            Value::Struct { fields, .. } => {
+                fields.iter().all(Value::is_hashable)
+            }
+            _ => true,
+        }
+    }
+
+    /// The dict to change, copied first if it is shared with another value.
+    pub fn as_dict(&mut self) -> &mut Dict {
+        match self {
+            Value::Dict(ref mut d) => Rc::make_mut(d),
             _ => unreachable!(),
         }
     }
 
-    pub fn as_func(
-        &self,
-    ) -> &fn(&mut Value, &mut Environment) -> Result<Value, LCoreError> {
+    pub fn as_func(&self) -> &Rc<NativeFunc> {
         match self {
-            Value::Func { f } => return f,
+            Value::Func(f) => f,
             _ => unreachable!(),
         }
     }
 
-    pub fn as_value(&self) -> &Value {
+    pub fn as_quote(&self) -> &Expr {
         match self {
-            Value::Quote(ref q) => return &(**q),
+            Value::Quote(ref q) => q,
             _ => unreachable!(),
         }
     }
 }
 
 impl Hash for Value {
+    /// Hashes agree with `PartialEq`. Values that are only equal to
+    /// themselves, such as functions, hash by their address, and a dict
+    /// hashes by its kind alone since `is_hashable` keeps it out of keys.
     fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
         match self {
-            Value::Null => Value::Null.hash(state),
-
+            Value::Null | Value::Dict(_) => (),
+            Value::Identifier(v) => v.hash(state),
+            Value::Boolean(v) => v.hash(state),
             Value::Int(v) => v.hash(state),
 
-            _ => (),
+            // NOTE(pebaz): 0.0 and -0.0 are equal, so they must hash alike.
+            Value::Float(v) => {
+                let v = if *v == 0.0 { 0.0 } else { *v };
+                v.to_bits().hash(state)
+            }
+
+            Value::String(v) => v.hash(state),
+            Value::Array(v) => v.hash(state),
+            Value::Quote(v) => v.hash(state),
+            Value::Func(v) => Rc::as_ptr(v).hash(state),
+            Value::Lambda(v) | Value::Macro(v) => Rc::as_ptr(v).hash(state),
+            Value::Type(v) => Rc::as_ptr(v).hash(state),
+            Value::Struct { kind, fields } => {
+                Rc::as_ptr(kind).hash(state);
+                fields.hash(state);
+            }
         }
     }
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
-        return match (self, other) {
+        match (self, other) {
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Int(a), Value::Int(b)) => a == b,
             (Value::Float(a), Value::Float(b)) => a == b,
@@ -144,10 +380,15 @@ impl PartialEq for Value {
             (Value::Identifier(a), Value::Identifier(b)) => a == b,
             (Value::Null, Value::Null) => true,
             (Value::Quote(a), Value::Quote(b)) => a == b,
-            (Value::Func { f: a }, Value::Func { f: b }) => {
-                a as *const _ == b as *const _
-            }
+            (Value::Func(a), Value::Func(b)) => Rc::ptr_eq(a, b),
             (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Lambda(a), Value::Lambda(b)) => Rc::ptr_eq(a, b),
+            (Value::Macro(a), Value::Macro(b)) => Rc::ptr_eq(a, b),
+            (Value::Type(a), Value::Type(b)) => Rc::ptr_eq(a, b),
+            (
+                Value::Struct { kind: a, fields: x },
+                Value::Struct { kind: b, fields: y },
+            ) => Rc::ptr_eq(a, b) && x == y,
 
             (Value::Dict(a), Value::Dict(b)) => a == b,
             // {
@@ -162,7 +403,7 @@ impl PartialEq for Value {
             // true
             // }
             _ => false,
-        };
+        }
     }
 }
 
@@ -172,40 +413,170 @@ impl fmt::Debug for Value {
     fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Null => write!(fm, "Null"),
-            Value::Identifier(i) => write!(fm, "Identifier"),
-            Value::Boolean(b) => write!(fm, "Boolean"),
-            Value::Int(i) => write!(fm, "Int"),
-            Value::Float(fl) => write!(fm, "Float"),
-            Value::String(s) => write!(fm, "String"),
-            Value::Array(a) => write!(fm, "Array"),
-            Value::OpenFunc => write!(fm, "("),
-            Value::CloseFunc => write!(fm, ")"),
-            Value::OpenBrace => write!(fm, "["),
-            Value::CloseBrace => write!(fm, "]"),
-            Value::Quote(b) => write!(fm, "'"),
-            Value::BackTick => write!(fm, "`"),
-            Value::Comma => write!(fm, ","),
-            Value::Func { f } => write!(fm, "Func"),
-            Value::Dict(h) => write!(fm, "Dict"),
+            Value::Identifier(_) => write!(fm, "Identifier"),
+            Value::Boolean(_) => write!(fm, "Boolean"),
+            Value::Int(_) => write!(fm, "Int"),
+            Value::Float(_) => write!(fm, "Float"),
+            Value::String(_) => write!(fm, "String"),
+            Value::Array(_) => write!(fm, "Array"),
+            Value::Quote(_) => write!(fm, "'"),
+            Value::Func(_) => write!(fm, "Func"),
+            Value::Dict(_) => write!(fm, "Dict"),
+            Value::Lambda(_) => write!(fm, "Lambda"),
+            Value::Macro(_) => write!(fm, "Macro"),
+            Value::Type(_) => write!(fm, "Type"),
+            Value::Struct { .. } => write!(fm, "Struct"),
+        }
+    }
+}
+
+/// One level of variables along with the scope it is nested in. Functions
+/// keep a reference to the scope they were defined in so that they can see
+/// its variables long after it has been popped off of the environment.
+pub struct Scope {
+    vars: SymTab,
+
+    /// Variables the compiler found a place for ahead of time, named by
+    /// `slot_names`. A slot is `None` until its variable is defined.
+    slots: Vec<Option<Value>>,
+    slot_names: Rc<[Symbol]>,
+
+    parent: Option<Rc<RefCell<Scope>>>,
+}
 
-            Value::Struct { name, fields } => write!(fm, "Struct"),
+impl Scope {
+    pub fn new(parent: Option<Rc<RefCell<Scope>>>) -> Rc<RefCell<Scope>> {
+        Scope::with_slots(parent, Rc::from(Vec::new()))
+    }
+
+    /// Create a scope that keeps the variables in `slot_names` in slots.
+    pub fn with_slots(
+        parent: Option<Rc<RefCell<Scope>>>,
+        slot_names: Rc<[Symbol]>,
+    ) -> Rc<RefCell<Scope>> {
+        Rc::new(RefCell::new(Scope {
+            vars: SymTab::default(),
+            slots: vec![None; slot_names.len()],
+            slot_names,
+            parent,
+        }))
+    }
+
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        self.slot_names.iter().position(|&slot| slot == name)
+    }
+
+    fn get(&self, name: Symbol) -> Option<&Value> {
+        match self.slot(name) {
+            Some(slot) => self.slots[slot].as_ref(),
+            None if self.vars.is_empty() => None,
+            None => self.vars.get(&name),
         }
     }
+
+    fn get_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        match self.slot(name) {
+            Some(slot) => self.slots[slot].as_mut(),
+            None => self.vars.get_mut(&name),
+        }
+    }
+
+    fn define(&mut self, name: Symbol, value: Value) {
+        match self.slot(name) {
+            Some(slot) => self.slots[slot] = Some(value),
+            None => {
+                self.vars.insert(name, value);
+            }
+        }
+    }
+
+    /// Every variable defined in this scope.
+    fn variables(&self) -> SymTab {
+        let mut vars = self.vars.clone();
+        for (&name, value) in self.slot_names.iter().zip(&self.slots) {
+            if let Some(value) = value {
+                vars.insert(name, value.clone());
+            }
+        }
+        vars
+    }
+}
+
+/// The code of a block run by a builtin, kept so that the block is only
+/// compiled the first time it is run.
+struct CompiledBlock {
+    /// Keeps the block's address from being reused by another block while it
+    /// is in the cache.
+    block: Weak<Expr>,
+
+    chunk: Rc<Chunk>,
 }
 
 pub struct Environment {
-    scopes: Vec<SymTab>,
+    scope: Rc<RefCell<Scope>>,
+    frames: Vec<Rc<RefCell<Scope>>>,
+    expansions: usize,
     return_vals: Vec<Value>,
+
+    /// Compiled blocks by the address of the block and whether it was
+    /// compiled for tail position.
+    blocks: HashMap<(*const Expr, bool), CompiledBlock>,
+
+    /// How many blocks can be cached before those that are gone are dropped.
+    blocks_limit: usize,
+
+    /// The calls that led to the error currently unwinding the stack, from
+    /// the innermost outward.
+    traceback: Vec<TraceFrame>,
+
+    /// The pending function and arguments of a call made in tail position.
+    pub tail_call: Option<(Value, Value)>,
+
+    /// Set while calling a native function in tail position.
+    pub tail_position: bool,
+
+    /// The struct type of the errors caught by `try`.
+    pub error_type: Rc<StructType>,
+
+    /// Where `print` and `prin` write to. Standard output by default.
+    pub output: Rc<RefCell<dyn Write>>,
+
+    /// Where `input` reads lines from. Standard input by default.
+    pub input: Rc<RefCell<dyn BufRead>>,
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            scopes: Vec::new(),
+            scope: Scope::new(None),
+            frames: Vec::new(),
+            expansions: 0,
+            blocks: HashMap::new(),
+            blocks_limit: LCORE_BLOCK_CACHE_SIZE,
+            traceback: Vec::new(),
             return_vals: vec![Value::Null],
+            tail_call: None,
+            tail_position: false,
+            error_type: Rc::new(StructType {
+                name: "error".to_string(),
+                fields: vec!["kind".to_string(), "message".to_string()],
+            }),
+            output: Rc::new(RefCell::new(io::stdout())),
+            input: Rc::new(RefCell::new(io::BufReader::new(io::stdin()))),
         }
     }
 
+    /// Create an empty environment that reads and writes to the same streams
+    /// as this one. Errors made with `new` in either can be raised in the
+    /// other since they share the `error` type too.
+    pub fn share_streams(&self) -> Environment {
+        let mut symbol_table = Environment::new();
+        symbol_table.output = self.output.clone();
+        symbol_table.input = self.input.clone();
+        symbol_table.error_type = self.error_type.clone();
+        symbol_table
+    }
+
     pub fn current_ret_index(&self) -> usize {
         self.return_vals.len() - 1
     }
@@ -221,70 +592,321 @@ impl Environment {
         self.return_vals.push(value);
     }
 
-    // fn get_iter(&mut self) -> i32 {
-    //
-    // }
-
     pub fn extend(&mut self, table: SymTab) {
-        let current = self.scopes.last_mut().unwrap();
-        current.extend(table);
+        let mut scope = self.scope.borrow_mut();
+        for (name, value) in table {
+            scope.define(name, value);
+        }
     }
 
+    /// The number of scopes visible from the current one.
+    #[allow(clippy::len_without_is_empty)] // Always has the global scope
     pub fn len(&self) -> usize {
-        self.scopes.len()
+        let mut count = 1;
+        let mut scope = self.scope.borrow().parent.clone();
+        while let Some(current) = scope {
+            count += 1;
+            scope = current.borrow().parent.clone();
+        }
+        count
     }
 
     pub fn push(&mut self) {
-        self.scopes.push(SymTab::new());
+        self.scope = Scope::new(Some(self.scope.clone()));
     }
 
     pub fn pop(&mut self) -> SymTab {
-        self.scopes.pop().unwrap()
+        let parent = self.scope.borrow().parent.clone().unwrap();
+        let scope = std::mem::replace(&mut self.scope, parent);
+
+        // NOTE(pebaz): A function may have captured this scope, so only take
+        // the variables if nothing else refers to them.
+        match Rc::try_unwrap(scope) {
+            Ok(scope) if scope.borrow().slots.is_empty() => {
+                scope.into_inner().vars
+            }
+            Ok(scope) => scope.borrow().variables(),
+            Err(scope) => scope.borrow().variables(),
+        }
+    }
+
+    /// The innermost scope, for functions to capture at definition time.
+    pub fn capture(&self) -> Rc<RefCell<Scope>> {
+        self.scope.clone()
+    }
+
+    /// Enter a scope that keeps the variables in `slot_names` in slots, such
+    /// as the scope of a loop.
+    pub fn push_slots(&mut self, slot_names: Rc<[Symbol]>) {
+        self.scope = Scope::with_slots(Some(self.scope.clone()), slot_names);
+    }
+
+    /// Return to a scope captured before pushing others on top of it.
+    pub fn restore(&mut self, scope: Rc<RefCell<Scope>>) {
+        self.scope = scope;
     }
 
-    pub fn insert(&mut self, key: String, value: Value) {
+    /// Enter a call frame whose parent is the scope the function was defined
+    /// in rather than the scope of the caller.
+    pub fn push_frame(
+        &mut self,
+        parent: Rc<RefCell<Scope>>,
+        slot_names: Rc<[Symbol]>,
+    ) {
+        let frame = Scope::with_slots(Some(parent), slot_names);
+        let caller = std::mem::replace(&mut self.scope, frame);
+        self.frames.push(caller);
+    }
+
+    /// The compiled code of a block, compiled the first time it is run.
+    pub fn compile_block(
+        &mut self,
+        block: &Rc<Expr>,
+        tail: bool,
+    ) -> Rc<Chunk> {
+        let key = (Rc::as_ptr(block), tail);
+        if let Some(compiled) = self.blocks.get(&key) {
+            return compiled.chunk.clone();
+        }
+
+        // NOTE(pebaz): Code made at run time, such as by a quasi-quote, can
+        // make any number of blocks, so those that are gone are dropped.
+        if self.blocks.len() >= self.blocks_limit {
+            self.blocks
+                .retain(|_, compiled| compiled.block.strong_count() > 0);
+            self.blocks_limit =
+                LCORE_BLOCK_CACHE_SIZE.max(self.blocks.len() * 2);
+        }
+
+        let chunk = Rc::new(match &**block {
+            Expr::Array(_, forms) => lcore_compile_block(forms, tail),
+            other => lcore_compile_block(slice::from_ref(other), tail),
+        });
+
+        self.blocks.insert(
+            key,
+            CompiledBlock {
+                block: Rc::downgrade(block),
+                chunk: chunk.clone(),
+            },
+        );
+        chunk
+    }
+
+    /// Check (and reset) whether the native function being called is in
+    /// tail position.
+    pub fn take_tail_position(&mut self) -> bool {
+        std::mem::replace(&mut self.tail_position, false)
+    }
+
+    /// Check if a user-defined function is currently being called.
+    pub fn in_frame(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// The number of function calls and macro expansions currently being
+    /// run.
+    pub fn depth(&self) -> usize {
+        self.frames.len() + self.expansions
+    }
+
+    /// Leave the current call frame and return to the caller's scope.
+    pub fn pop_frame(&mut self) {
+        self.scope = self.frames.pop().unwrap();
+    }
+
+    /// Note where an error happened, unless it is already known for the
+    /// function being run.
+    pub fn trace_error(&mut self, span: Option<Span>) {
+        let located = match self.traceback.last() {
+            Some(frame) => frame.function.is_none(),
+            None => false,
+        };
+
+        if let (false, Some(span)) = (located, span) {
+            self.traceback.push(TraceFrame {
+                span,
+                function: None,
+            });
+        }
+    }
+
+    /// Attribute the innermost location of an error to the function it
+    /// happened in once the error leaves that function.
+    pub fn trace_function(&mut self, name: &str) {
+        if let Some(frame) = self.traceback.last_mut() {
+            if frame.function.is_none() {
+                frame.function = Some(name.to_string());
+            }
+        }
+    }
+
+    /// The calls that led to the last error, from the outermost inward.
+    pub fn take_traceback(&mut self) -> Vec<TraceFrame> {
+        let mut traceback = std::mem::take(&mut self.traceback);
+        traceback.reverse();
+        traceback
+    }
+
+    /// Define a variable in the innermost scope, shadowing any outer one.
+    pub fn define(&mut self, key: impl Into<Symbol>, value: Value) {
+        self.scope.borrow_mut().define(key.into(), value);
+    }
+
+    pub fn insert(&mut self, key: impl Into<Symbol>, value: Value) {
         // NOTE(pebaz): Need to check if the var exists first. If it exists in
         // another block, set that var's value, don't define a new one.
+        let key = key.into();
 
-        if !self.contains_key(key.clone()) {
-            let scope = self.scopes.last_mut().unwrap();
-            scope.insert(key, value);
-        } else {
-            if let Some(already_there) = self.get(key.clone()) {
-                *already_there = value;
-            }
+        let found = self.with_value(key, |already_there| {
+            *already_there = value.clone();
+        });
+
+        if found.is_none() {
+            self.define(key, value);
         }
     }
 
-    pub fn contains_key(&self, name: String) -> bool {
-        for scope in self.scopes.iter().rev() {
-            // println!("{:p}: ", scope);
-            if let Some(value) = scope.get(&name) {
-                return true;
-            }
+    pub fn contains_key(&self, name: impl Into<Symbol>) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Value> {
+        lcore_find(Some(self.scope.clone()), name.into())
+    }
+
+    /// Get the value of a variable, failing if there is none.
+    pub fn lookup(
+        &self,
+        name: impl Into<Symbol>,
+    ) -> Result<Value, LCoreError> {
+        let name = name.into();
+        lcore_find(Some(self.scope.clone()), name).ok_or_else(|| {
+            LCoreError::NameError(format!("Cannot lookup name: \"{}\"", name))
+        })
+    }
+
+    /// The scope `depth` levels out from the innermost one.
+    fn scope_at(&self, depth: usize) -> Rc<RefCell<Scope>> {
+        let mut scope = self.scope.clone();
+        for _ in 0..depth {
+            let parent = scope.borrow().parent.clone().unwrap();
+            scope = parent;
         }
-        false
+        scope
     }
 
-    pub fn get(&mut self, name: String) -> Option<&mut Value> {
-        for scope in &mut self.scopes.iter_mut().rev() {
-            if let Some(value) = scope.get_mut(&name) {
-                return Some(value);
+    /// Get the variable in a slot of the scope `depth` levels out. If it has
+    /// not been defined there yet, it is looked up by name instead.
+    pub fn get_local(
+        &self,
+        depth: usize,
+        slot: usize,
+    ) -> Result<Value, LCoreError> {
+        let scope = self.scope_at(depth);
+        let scope = scope.borrow();
+        match &scope.slots[slot] {
+            Some(value) => Ok(value.clone()),
+            None => self.lookup(scope.slot_names[slot]),
+        }
+    }
+
+    /// Set the variable in a slot of the scope `depth` levels out. If it has
+    /// not been defined there yet, it is set by name with `insert` instead.
+    pub fn set_local(&mut self, depth: usize, slot: usize, value: Value) {
+        let name = {
+            let scope = self.scope_at(depth);
+            let mut scope = scope.borrow_mut();
+            if let Some(current) = scope.slots[slot].as_mut() {
+                *current = value;
+                return;
             }
+            scope.slot_names[slot]
+        };
+
+        self.insert(name, value);
+    }
+
+    /// Define the variable in a slot of the innermost scope.
+    pub fn bind_slot(&mut self, slot: usize, value: Value) {
+        self.scope.borrow_mut().slots[slot] = Some(value);
+    }
+
+    /// Run `f` on the variable named `name` in whichever scope it lives in.
+    pub fn with_value<R>(
+        &mut self,
+        name: impl Into<Symbol>,
+        f: impl FnOnce(&mut Value) -> R,
+    ) -> Option<R> {
+        let name = name.into();
+        let mut scope = Some(self.scope.clone());
+        while let Some(current) = scope {
+            if let Some(value) = current.borrow_mut().get_mut(name) {
+                return Some(f(value));
+            }
+            scope = current.borrow().parent.clone();
         }
         None
     }
 }
 
+/// Find a variable in a scope or any of the scopes it is nested in.
+fn lcore_find(
+    mut scope: Option<Rc<RefCell<Scope>>>,
+    name: Symbol,
+) -> Option<Value> {
+    while let Some(current) = scope {
+        if let Some(value) = current.borrow().get(name) {
+            return Some(value.clone());
+        }
+        scope = current.borrow().parent.clone();
+    }
+    None
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum LCoreError {
     LambdaCoreError(String),
     IndexError(String),
     ArgumentError(String),
     NameError(String),
+
+    /// A value of the wrong type was used.
+    TypeError(String),
+
+    /// A dict has no such key or a struct has no such field.
+    KeyError(String),
+
+    ZeroDivisionError(String),
+
+    /// A function was called with the wrong number of arguments.
+    ArityError(String),
+
+    /// A module could not be imported.
+    ImportError(String),
+
+    /// A file could not be read or written.
+    IOError(String),
+
+    /// Source code could not be parsed (explanation, where it happened).
+    ParseError(String, Option<Box<ParseLocation>>),
+
+    /// An error raised from a script with a kind of its own (kind, message).
+    UserError(String, String),
+
     // ReturnError(Value),
     ReturnError,
     BreakError,
+    TailCallError,
+
+    /// `exit` was called with a status for the process to end with.
+    ExitError(i32),
 }
 
 impl LCoreError {
@@ -304,6 +926,14 @@ impl LCoreError {
         Err(LCoreError::NameError(msg))
     }
 
+    pub fn Type(msg: String) -> Result<Value, LCoreError> {
+        Err(LCoreError::TypeError(msg))
+    }
+
+    pub fn Key(msg: String) -> Result<Value, LCoreError> {
+        Err(LCoreError::KeyError(msg))
+    }
+
     // pub fn Return(val: Value) -> Result<Value, LCoreError> {
     pub fn Return() -> Result<Value, LCoreError> {
         // Err(LCoreError::ReturnError(val))
@@ -313,352 +943,563 @@ impl LCoreError {
     pub fn Break() -> Result<Value, LCoreError> {
         Err(LCoreError::BreakError)
     }
-}
 
+    /// Create the error for a kind named in a script, using the builtin
+    /// variants where possible.
+    pub fn from_kind(kind: &str, message: &str) -> LCoreError {
+        let msg = message.to_string();
+        match kind {
+            "LambdaCoreError" => LCoreError::LambdaCoreError(msg),
+            "IndexError" => LCoreError::IndexError(msg),
+            "ArgumentError" => LCoreError::ArgumentError(msg),
+            "NameError" => LCoreError::NameError(msg),
+            "TypeError" => LCoreError::TypeError(msg),
+            "KeyError" => LCoreError::KeyError(msg),
+            "ZeroDivisionError" => LCoreError::ZeroDivisionError(msg),
+            "ArityError" => LCoreError::ArityError(msg),
+            "ImportError" => LCoreError::ImportError(msg),
+            "IOError" => LCoreError::IOError(msg),
+            "ParseError" => LCoreError::ParseError(msg, None),
+            _ => LCoreError::UserError(kind.to_string(), msg),
+        }
+    }
 
-/// Turn tokens into intermediate code.
-///
-/// Returns: The count of the lines of code in the file.
-pub fn lcore_parse(
-    node: Pair<'_, Rule>,
-    stack: &mut VecDeque<Value>,
-) -> usize {
-    let mut loc = 0;
-
-    match node.as_rule() {
-        Rule::Program => {
-            for rule in node.into_inner() {
-                loc += lcore_parse(rule, stack);
-            }
+    pub fn kind(&self) -> &str {
+        match self {
+            LCoreError::LambdaCoreError(_) => "LambdaCoreError",
+            LCoreError::IndexError(_) => "IndexError",
+            LCoreError::ArgumentError(_) => "ArgumentError",
+            LCoreError::NameError(_) => "NameError",
+            LCoreError::TypeError(_) => "TypeError",
+            LCoreError::KeyError(_) => "KeyError",
+            LCoreError::ZeroDivisionError(_) => "ZeroDivisionError",
+            LCoreError::ArityError(_) => "ArityError",
+            LCoreError::ImportError(_) => "ImportError",
+            LCoreError::IOError(_) => "IOError",
+            LCoreError::ParseError(..) => "ParseError",
+            LCoreError::UserError(kind, _) => kind,
+            LCoreError::ReturnError => "ReturnError",
+            LCoreError::BreakError => "BreakError",
+            LCoreError::TailCallError => "TailCallError",
+            LCoreError::ExitError(_) => "ExitError",
         }
+    }
 
-        Rule::Function => {
-            stack.push_back(Value::OpenFunc);
-            let mut rules = node.into_inner();
-
-            let func = match rules.next() {
-                Some(rule) => {
-                    stack.push_back(Value::Identifier(String::from(
-                        rule.as_str(),
-                    )));
-                }
-                _ => unreachable!(),
-            };
+    pub fn message(&self) -> &str {
+        match self {
+            LCoreError::LambdaCoreError(msg)
+            | LCoreError::IndexError(msg)
+            | LCoreError::ArgumentError(msg)
+            | LCoreError::NameError(msg)
+            | LCoreError::TypeError(msg)
+            | LCoreError::KeyError(msg)
+            | LCoreError::ZeroDivisionError(msg)
+            | LCoreError::ArityError(msg)
+            | LCoreError::ImportError(msg)
+            | LCoreError::IOError(msg)
+            | LCoreError::ParseError(msg, _)
+            | LCoreError::UserError(_, msg) => msg,
+            _ => "",
+        }
+    }
 
-            for rule in rules {
-                loc += lcore_parse(rule, stack);
-            }
-            stack.push_back(Value::CloseFunc);
+    /// Errors used to unwind the stack for `ret`, `break`, `exit` and tail
+    /// calls rather than to report a problem.
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            LCoreError::ReturnError
+                | LCoreError::BreakError
+                | LCoreError::TailCallError
+                | LCoreError::ExitError(_)
+        )
+    }
+
+    /// The status the process should exit with when this error is not
+    /// caught.
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            LCoreError::ExitError(status) => *status,
+            LCoreError::ParseError(..) => LCORE_EXIT_PARSE_ERROR,
+            _ => LCORE_EXIT_RUNTIME_ERROR,
         }
+    }
+}
+
+/// Where a program could not be parsed, along with the text of that line so
+/// that it can be shown.
+#[derive(Debug, Clone)]
+pub struct ParseLocation {
+    pub file: String,
 
-        Rule::Array => {
-            // stack.push_back(Value::OpenBrace);
+    /// The line and column, both counting from 1.
+    pub line: usize,
+    pub column: usize,
 
-            let mut array_stack = VecDeque::new();
+    pub text: String,
+}
 
-            for rule in node.into_inner() {
-                // loc += lcore_parse(rule, stack);
-                loc += lcore_parse(rule, &mut array_stack);
-            }
+impl ParseLocation {
+    /// The space to the left of the `|` that the line number goes in.
+    pub fn gutter(&self) -> String {
+        " ".repeat(self.line.to_string().len())
+    }
+
+    /// The space before the column on the line, keeping tabs so that a caret
+    /// after it lines up with the text.
+    pub fn indent(&self) -> String {
+        self.text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect()
+    }
+}
 
-            let mut new_array = Vec::new();
-            new_array.extend(array_stack);
-            stack.push_back(Value::Array(new_array));
+impl fmt::Display for ParseLocation {
+    /// Show the line with a caret under the column.
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = self.gutter();
+        write!(
+            fm,
+            "{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}^",
+            gutter,
+            self.file,
+            self.line,
+            self.column,
+            gutter,
+            self.line,
+            self.text,
+            gutter,
+            self.indent()
+        )
+    }
+}
+
+impl fmt::Display for LCoreError {
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        write!(fm, "{}: {}", self.kind(), self.message())
+    }
+}
 
-            // stack.extend(array_stack);
-            // stack.push_back(Value::CloseBrace);
+/// Turn a parsed program into the forms it is made of.
+pub fn lcore_parse(
+    node: Pair<'_, Rule>,
+    source: &Rc<Source>,
+) -> Result<Vec<Expr>, Box<Error<Rule>>> {
+    lcore_parse_forms(node.into_inner(), source, false)
+}
+
+/// Turn a list of parsed forms into their trees, skipping comments.
+fn lcore_parse_forms(
+    nodes: Pairs<'_, Rule>,
+    source: &Rc<Source>,
+    template: bool,
+) -> Result<Vec<Expr>, Box<Error<Rule>>> {
+    let mut forms = Vec::new();
+
+    for rule in nodes {
+        if let Some(form) = lcore_parse_form(rule, source, template)? {
+            forms.push(form);
         }
+    }
+
+    Ok(forms)
+}
+
+/// Turn one parsed form into its tree. Comments and newlines have none.
+///
+/// `template` is whether the form is inside of a quasi-quote, which is the
+/// only place an unquote can be used.
+fn lcore_parse_form(
+    node: Pair<'_, Rule>,
+    source: &Rc<Source>,
+    template: bool,
+) -> Result<Option<Expr>, Box<Error<Rule>>> {
+    let span = source.span(node.as_span().start(), node.as_span().end());
+
+    let form = match node.as_rule() {
+        Rule::Function => {
+            let rules = node.into_inner();
+
+            // NOTE(pebaz): The function is either a name or an unquote
+            let items = lcore_parse_forms(rules, source, template)?;
+            Expr::Call(span, Rc::from(items))
+        }
+
+        Rule::Array => Expr::Array(
+            span,
+            lcore_parse_forms(node.into_inner(), source, template)?,
+        ),
 
         Rule::Number => {
-            if node.as_str().contains(".") {
-                stack.push_back(Value::Float(
-                    FromStr::from_str(node.as_str()).unwrap(),
-                ))
+            let value = if node.as_str().contains(".") {
+                Value::Float(FromStr::from_str(node.as_str()).unwrap())
             } else {
-                stack.push_back(Value::Int(
-                    FromStr::from_str(node.as_str()).unwrap(),
-                ))
-            }
+                // NOTE(pebaz): Integers too big for an Int become Floats
+                match i64::from_str(node.as_str()) {
+                    Ok(i) => Value::Int(i),
+                    Err(_) => {
+                        Value::Float(FromStr::from_str(node.as_str()).unwrap())
+                    }
+                }
+            };
+            Expr::Literal(span, value)
         }
 
         Rule::Quote => {
-            // stack.push_back(Value::Quote)
-
-            let mut quote_stack = VecDeque::new();
+            let quoted =
+                lcore_parse_forms(node.into_inner(), source, template)?
+                    .pop()
+                    .unwrap();
+
+            // NOTE(pebaz): A quoted function call becomes a block of code
+            // just like `'[(...)]`.
+            match quoted {
+                Expr::Call(..) => {
+                    let block =
+                        Expr::Array(quoted.span().clone(), vec![quoted]);
+                    Expr::Quote(span, Rc::new(block))
+                }
+                quoted => Expr::Quote(span, Rc::new(quoted)),
+            }
+        }
 
-            // TODO(pebaz): NEED TO NEST ALL OTHER VALUES WITHIN ALL TYPES OF
-            // QUOTES :/
+        Rule::BackTick => {
+            let quoted = lcore_parse_forms(node.into_inner(), source, true)?
+                .pop()
+                .unwrap();
+            Expr::QuasiQuote(span, Rc::new(quoted))
+        }
 
-            for rule in node.into_inner() {
-                loc += lcore_parse(rule, &mut quote_stack);
+        Rule::Comma | Rule::CommaAt => {
+            if !template {
+                return Err(Box::new(Error::new_from_span(
+                    CustomError {
+                        message: "unquote used outside of a quasi-quote"
+                            .to_string(),
+                    },
+                    node.as_span(),
+                )));
             }
 
-            assert!(quote_stack.len() == 1);
+            let splice = node.as_rule() == Rule::CommaAt;
+            let form = lcore_parse_forms(node.into_inner(), source, false)?
+                .pop()
+                .unwrap();
 
-            stack.push_back(Value::Quote(Box::new(
-                quote_stack.pop_back().unwrap(),
-            )));
-
-            // let mut new_array = Vec::new();
-            // new_array.extend(quote_stack);
-            // stack.push_back(Value::Array(new_array));
+            if splice {
+                Expr::Splice(span, Box::new(form))
+            } else {
+                Expr::Unquote(span, Box::new(form))
+            }
         }
 
         Rule::String => {
-            let mut string = String::from(node.as_str());
-            string = string[1..string.len() - 1].to_string();
-            stack.push_back(Value::String(string))
+            let string = node.as_str();
+            Expr::Literal(
+                span,
+                Value::String(Rc::new(
+                    string[1..string.len() - 1].to_string(),
+                )),
+            )
         }
 
-        Rule::BackTick => stack.push_back(Value::BackTick),
-        Rule::Comma => stack.push_back(Value::Comma),
-        Rule::Identifier => {
-            stack.push_back(Value::Identifier(String::from(node.as_str())))
-        }
-        Rule::Boolean => stack.push_back(Value::Boolean(
-            FromStr::from_str(node.as_str().to_lowercase().as_str()).unwrap(),
-        )),
-        Rule::Null => stack.push_back(Value::Null),
-        Rule::NewLine => loc += 1,
-        Rule::EOI => {} // May want to use this for module imports :D
-        _ => (),
-    }
-
-    return loc;
+        Rule::Identifier => Expr::Symbol(span, Symbol::intern(node.as_str())),
+        Rule::Boolean => Expr::Literal(
+            span,
+            Value::Boolean(
+                FromStr::from_str(node.as_str().to_lowercase().as_str())
+                    .unwrap(),
+            ),
+        ),
+        Rule::Null => Expr::Literal(span, Value::Null),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(form))
 }
 
-/// Interpret a LambdaCore Program.
+/// Compile and run a LambdaCore Program in the current scope.
 pub fn lcore_interpret(
-    stack: &mut VecDeque<Value>,
+    forms: &[Expr],
     symbol_table: &mut Environment,
 ) -> Result<Value, LCoreError> {
-    let mut arrays: Vec<Value> = Vec::with_capacity(64);
-
-    // NOTE(pebaz): Since a function can be called in the global scope, we need
-    // a top-level array to catch any global function call return values.
-    arrays.push(Value::Array(Vec::new()));
+    lcore_run(&lcore_compile_block(forms, false), symbol_table)
+}
 
-    while let Some(node) = stack.pop_front() {
-        if let Err(error) =
-            lcore_interpret_expression(stack, symbol_table, &mut arrays, node)
-        {
-            return Err(error);
+/// Build a quoted value out of a quasi-quoted template, evaluating anything
+/// that has been unquoted with `,` and splicing anything unquoted with `,@`.
+pub fn lcore_quasi_quote(
+    template: &Expr,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let mut filled = Vec::with_capacity(1);
+    lcore_fill(template, &mut filled, symbol_table)?;
+
+    // NOTE(pebaz): A template of a lone array or name is quoted as-is, but a
+    // function call becomes a block of code that can be run later.
+    let quoted = match template {
+        Expr::Call(..) | Expr::Unquote(..) | Expr::Splice(..) => {
+            Expr::Array(template.span().clone(), filled)
         }
-    }
+        _ => filled.pop().unwrap(),
+    };
 
-    // Return the value from the last function to be called
-    let mut last_array = arrays.pop().unwrap();
-    match last_array {
-        Value::Array(ref mut v) => match v.pop() {
-            Some(e) => return Ok(e),
-            None => return Ok(Value::Null),
-        },
-        _ => unreachable!(),
-    }
+    Ok(Value::Quote(Rc::new(quoted)))
 }
 
-pub fn lcore_interpret_expression(
-    stack: &mut VecDeque<Value>,
+/// Fill in every unquote in a template, including those in nested arrays and
+/// quotes, adding the result to `out`. A splice can add any number of forms.
+fn lcore_fill(
+    template: &Expr,
+    out: &mut Vec<Expr>,
     symbol_table: &mut Environment,
-    arrays: &mut Vec<Value>,
-    node: Value,
-) -> Result<Value, LCoreError> {
-    match node {
-        Value::Identifier(ref v) => {
-            if LCORE_DEBUG {
-                println!("Identifier: {}", node.as_identifier());
-            }
+) -> Result<(), LCoreError> {
+    let filled = match template {
+        Expr::Unquote(span, form) => {
+            let value =
+                lcore_interpret(slice::from_ref(&**form), symbol_table)?;
+            lcore_value_expr(value, span)
+        }
 
-            let length = arrays.len();
+        Expr::Splice(span, form) => {
+            match lcore_interpret(slice::from_ref(&**form), symbol_table)? {
+                Value::Array(v) => out.extend(
+                    Rc::unwrap_or_clone(v)
+                        .into_iter()
+                        .map(|item| lcore_value_expr(item, span)),
+                ),
+                Value::Quote(q) => match &*q {
+                    Expr::Array(_, items) => out.extend(items.iter().cloned()),
+                    other => out.push(other.clone()),
+                },
+
+                // NOTE(pebaz): Splicing a single value (such as an
+                // identifier passed to a macro) just inserts it.
+                other => out.push(lcore_value_expr(other, span)),
+            }
+            return Ok(());
+        }
 
-            if let Value::Array(ref mut v) = arrays[length - 1] {
-                // Lookup the current node and push it
-                let key = node.as_identifier();
-                if !symbol_table.contains_key(key.as_str().to_string()) {
-                    return Err(LCoreError::NameError(format!(
-                        "NameError: Cannot lookup name: \"{}\"",
-                        key
-                    )));
-                }
+        Expr::Call(span, items) => {
+            let mut filled = Vec::with_capacity(items.len());
+            for item in items.iter() {
+                lcore_fill(item, &mut filled, symbol_table)?;
+            }
+            Expr::Call(span.clone(), Rc::from(filled))
+        }
 
-                v.push(
-                    symbol_table
-                        .get(key.as_str().to_string())
-                        .unwrap()
-                        .clone(),
-                )
+        Expr::Array(span, items) => {
+            let mut filled = Vec::with_capacity(items.len());
+            for item in items {
+                lcore_fill(item, &mut filled, symbol_table)?;
             }
+            Expr::Array(span.clone(), filled)
         }
 
-        Value::OpenFunc => {
-            arrays.push(Value::Array(Vec::new()));
+        Expr::Quote(span, quoted) | Expr::QuasiQuote(span, quoted) => {
+            let mut filled = Vec::with_capacity(1);
+            lcore_fill(quoted, &mut filled, symbol_table)?;
+            let filled = match filled.len() {
+                1 => filled.pop().unwrap(),
+                _ => Expr::Array(quoted.span().clone(), filled),
+            };
+
+            match template {
+                Expr::Quote(..) => Expr::Quote(span.clone(), Rc::new(filled)),
+                _ => Expr::QuasiQuote(span.clone(), Rc::new(filled)),
+            }
         }
 
-        Value::CloseFunc => {
-            // Call the function & store result in `arrays`
+        _ => template.clone(),
+    };
 
-            let length = arrays.len();
-            if let Value::Array(ref mut v) = arrays[length - 1] {
-                let func = v.remove(0);
-                let mut args = arrays.pop().unwrap();
+    out.push(filled);
+    Ok(())
+}
 
-                // IMPORTANT(pebaz): Either the func is a native function
-                // or a LambdaCore function.
+/// The unevaluated arguments of a macro call. Function calls are passed as
+/// quoted calls, so that unquoting one puts the call itself in the template,
+/// and everything else is passed as-is.
+pub fn lcore_macro_arguments(forms: &[Expr]) -> Vec<Value> {
+    forms.iter().map(lcore_expr_value).collect()
+}
 
-                let ret = match func {
-                    Value::Func { f } => f(&mut args, symbol_table),
+/// Run a macro on its unevaluated arguments, returning the code it generates.
+pub fn lcore_expand_macro(
+    mac: Rc<Lambda>,
+    args: Vec<Value>,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_call_lambda(mac, Value::Array(Rc::new(args)), symbol_table)
+}
 
-                    Value::Array(a) => {
-                        let arg_names = match &a[0] {
-                            Value::Array(argument_names) => argument_names,
-                            _ => unreachable!(),
-                        };
+/// The code to run in place of a macro call given the macro's expansion.
+/// Any code made from a value other than a quote is placed at the call.
+pub fn lcore_expansion_code(expansion: Value, span: &Span) -> Rc<Expr> {
+    match expansion {
+        Value::Quote(q) => q,
+        other => Rc::new(lcore_value_expr(other, span)),
+    }
+}
 
-                        // TODO(pebaz): In order to do Tail-Call
-                        // Optimization,
-                        // it is necessary to remove the next code line.
-                        // This will allow the function to reuse names
-                        // (and therefore storage) from previous call.
-
-                        // Push a new scope
-                        symbol_table.push();
-
-                        // Bind all arguments to the given values
-                        if let Value::Array(ref mut v) = args {
-                            let mut count = v.len();
-                            while let Some(value) = v.pop() {
-                                count -= 1; // Iterate in reverse
-                                match &arg_names[count] {
-                                    Value::Quote(v) => {
-                                        symbol_table.insert(
-                                            v.as_identifier().to_string(),
-                                            value,
-                                        );
-                                    }
-
-                                    _ => unreachable!(),
-                                }
-                            }
-                        }
+/// Expand a macro with its unevaluated arguments and run the code it
+/// generates. `span` is where the macro was called.
+pub fn lcore_call_macro(
+    mac: Rc<Lambda>,
+    args: Vec<Value>,
+    span: &Span,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let expansion = lcore_expand_macro(mac, args, symbol_table)?;
 
-                        let ret = match &a[1] {
-                            Value::Array(def) => {
-                                let mut body =
-                                    VecDeque::from_iter(def.clone());
-
-                                // lcore_interpret(&mut body, symbol_table)
-                                let return_point =
-                                    symbol_table.current_ret_index();
-                                let return_this =
-                                    lcore_interpret(&mut body, symbol_table);
-                                if symbol_table.current_ret_index()
-                                    > return_point
-                                {
-                                    let r = symbol_table
-                                        .pop_ret_index(return_point + 1);
-                                    Ok(r)
-                                } else {
-                                    return_this
-                                }
-                            }
-                            _ => unreachable!(),
-                        };
+    // NOTE(pebaz): A macro can expand to a call to itself
+    lcore_check_depth(symbol_table)?;
+    symbol_table.expansions += 1;
+    let value = lcore_interpret_block(
+        &lcore_expansion_code(expansion, span),
+        symbol_table,
+        false,
+    );
+    symbol_table.expansions -= 1;
+    value
+}
 
-                        // Reclaim all old variables
-                        symbol_table.pop();
+/// Call a native or user-defined function with already evaluated arguments.
+pub fn lcore_call(
+    func: Value,
+    args: Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    lcore_call_tail(func, args, symbol_table, false)
+}
 
-                        // Value::Null
-                        ret
-                    }
+/// Call a function, handing a call to a user-defined function in tail
+/// position back to the function being run via `LCoreError::TailCallError`
+/// so that it can reuse the current frame instead of growing the Rust stack.
+pub fn lcore_call_tail(
+    func: Value,
+    mut args: Value,
+    symbol_table: &mut Environment,
+    tail: bool,
+) -> Result<Value, LCoreError> {
+    match func {
+        Value::Func(f) => {
+            // NOTE(pebaz): Let `if` and `sel` know that their blocks are in
+            // tail position too.
+            symbol_table.tail_position = tail;
+            f.call(&mut args, symbol_table)
+        }
+        Value::Lambda(lambda) => {
+            if tail {
+                symbol_table.tail_call = Some((Value::Lambda(lambda), args));
+                Err(LCoreError::TailCallError)
+            } else {
+                lcore_call_lambda(lambda, args, symbol_table)
+            }
+        }
+        _ => Err(LCoreError::TypeError(format!("{:?} is not callable", func))),
+    }
+}
 
-                    _ => Ok(Value::Null),
-                };
+/// Fail instead of overflowing the stack on runaway recursion.
+fn lcore_check_depth(symbol_table: &Environment) -> Result<(), LCoreError> {
+    if symbol_table.depth() >= LCORE_MAX_DEPTH {
+        return Err(LCoreError::LambdaCoreError(format!(
+            "Maximum recursion depth of {} exceeded",
+            LCORE_MAX_DEPTH
+        )));
+    }
 
-                let length = arrays.len();
-                if let Value::Array(ref mut v) = arrays[length - 1] {
-                    // v.push(ret.ok().unwrap())
+    Ok(())
+}
 
-                    v.push(match ret {
-                        Ok(i) => i,
-                        Err(err) => return Err(err),
-                    });
-                }
-            }
+fn lcore_call_lambda(
+    mut lambda: Rc<Lambda>,
+    args: Value,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let mut args = match args {
+        Value::Array(v) => v,
+        _ => unreachable!(),
+    };
+
+    // NOTE(pebaz): Calls in tail position come back here instead of
+    // recursing, so each iteration of this loop reuses the same Rust stack
+    // frame.
+    loop {
+        let code = lambda.code.clone();
+
+        if args.len() != code.params.len() {
+            return Err(LCoreError::ArityError(format!(
+                "\"{}\" takes {} arguments but {} were given",
+                lambda.name.as_ref().map_or("fn", |n| n.as_str()),
+                code.params.len(),
+                args.len()
+            )));
         }
 
-        Value::Array(mut v) => {
-            arrays.push(Value::Array(Vec::new()));
+        lcore_check_depth(symbol_table)?;
 
-            let mut elements = VecDeque::with_capacity(v.len());
-            while let Some(element) = v.pop() {
-                elements.push_front(element);
-            }
+        // Push a new scope nested inside the one the function was defined in
+        symbol_table.push_frame(lambda.env.clone(), code.slots.clone());
 
-            while let Some(element) = elements.pop_front() {
-                let result = lcore_interpret_expression(
-                    stack,
-                    symbol_table,
-                    arrays,
-                    element,
-                );
-
-                if let Err(ref err) = result {
-                    match err {
-                        // LCoreError::ReturnError(v) => return
-                        // Err(err.clone()),
-                        LCoreError::ReturnError => return Err(err.clone()),
-                        LCoreError::BreakError => return Err(err.clone()),
-
-                        _ => return Err(err.clone()),
-                    }
-                }
-            }
+        // Bind all arguments to the given values
+        let values = Rc::unwrap_or_clone(args);
+        for (&slot, value) in code.param_slots.iter().zip(values) {
+            symbol_table.bind_slot(slot, value);
+        }
 
-            let resulting_array = arrays.pop().unwrap();
+        let return_point = symbol_table.current_ret_index();
+        let return_this = lcore_run(&code.chunk, symbol_table);
 
-            let length = arrays.len();
-            if let Value::Array(ref mut last) = arrays[length - 1] {
-                last.push(resulting_array);
+        if let Err(ref err) = return_this {
+            if !err.is_control_flow() {
+                let name = lambda.name.as_deref().unwrap_or("<lambda>");
+                symbol_table.trace_function(name);
             }
         }
+        let ret = if symbol_table.current_ret_index() > return_point {
+            Ok(symbol_table.pop_ret_index(return_point + 1))
+        } else {
+            return_this
+        };
 
-        // NOTE(pebaz): Put all the other tokens into the stack
-        _ => {
-            if let Value::Array(ref mut last) = arrays.last_mut().unwrap() {
-                last.push(node);
+        // Return to the caller's scope
+        symbol_table.pop_frame();
+
+        match ret {
+            Err(LCoreError::TailCallError) => {
+                let (func, next_args) = symbol_table.tail_call.take().unwrap();
+                match func {
+                    Value::Lambda(next) => {
+                        lambda = next;
+                        args = match next_args {
+                            Value::Array(v) => v,
+                            _ => unreachable!(),
+                        };
+                    }
+                    _ => return lcore_call(func, next_args, symbol_table),
+                }
             }
+            _ => return ret,
         }
     }
-
-    Ok(Value::Null)
 }
 
-pub fn lcore_interpret_array(
-    element: &Value,
+/// Run a block of code, returning the value of its last expression.
+///
+/// The block is only compiled the first time it is run, so a block run over
+/// and over by a builtin such as `loop` or `try` is no slower than one
+/// compiled in place.
+pub fn lcore_interpret_block(
+    block: &Rc<Expr>,
     symbol_table: &mut Environment,
+    tail: bool,
 ) -> Result<Value, LCoreError> {
-    let mut stack = VecDeque::new();
-    let mut arrays: Vec<Value> = Vec::new();
-    arrays.push(Value::Array(Vec::new()));
-
-    if let Err(error) = lcore_interpret_expression(
-        &mut stack,
-        symbol_table,
-        &mut arrays,
-        element.clone(),
-    ) {
-        // println!("--> {:?}", error);
-        return Err(error);
-    } else {
-        // NOTE(pebaz): Return the last value from the array
-        let mut array = arrays.pop().unwrap();
-        if let Value::Array(ref mut r) = array {
-            return Ok(r.pop().unwrap());
-        }
-
-        // There should always be an array at the end.
-        unreachable!();
-    }
+    let chunk = symbol_table.compile_block(block, tail);
+    lcore_run(&chunk, symbol_table)
 }
 
 pub fn count_newlines(s: &str) -> usize {
@@ -685,37 +1526,36 @@ pub fn lcore_repl() {
             let mut line = String::new();
             io::stdin().read_line(&mut line).unwrap();
             if add_space {
-                input.push_str(" ");
+                input.push(' ');
                 add_space = false;
             }
-            input.push_str(&line.trim());
+            input.push_str(line.trim());
 
             match LambdaCoreParser::parse(Rule::Program, &input) {
                 Ok(mut i) => {
-                    let mut stack = VecDeque::new();
-                    lcore_parse(i.next().unwrap(), &mut stack);
-
-                    // lcore_interpret(&mut stack, &mut symbol_table);
+                    let source = Rc::new(Source::new("<stdin>", &input));
+                    let forms = match lcore_parse(i.next().unwrap(), &source) {
+                        Ok(forms) => forms,
+                        Err(err) => {
+                            let err =
+                                lcore_parse_error(&input, "<stdin>", &err);
+                            lcore_report_error(&err, &mut symbol_table);
+                            break;
+                        }
+                    };
 
-                    match lcore_interpret(&mut stack, &mut symbol_table) {
+                    match lcore_interpret(&forms, &mut symbol_table) {
                         // NOTE(pebaz): Make sure errors are not lost
-                        Err(err) => match err {
-                            LCoreError::LambdaCoreError(s) => {
-                                println!("{}", s)
-                            }
-                            LCoreError::IndexError(s) => println!("{}", s),
-                            LCoreError::ArgumentError(s) => println!("{}", s),
-                            LCoreError::NameError(s) => println!("{}", s),
-
-                            // LCoreError::ReturnError(v) => println!("NOT
-                            // IMPLEMENTED ERROR"),
-                            LCoreError::ReturnError => {
-                                println!("NOT IMPLEMENTED ERROR")
-                            }
-                            LCoreError::BreakError => {
-                                println!("NOT IMPLEMENTED!")
+                        Err(err) => {
+                            let err =
+                                lcore_top_level_error(err, &mut symbol_table);
+                            match err {
+                                LCoreError::ExitError(status) => exit(status),
+                                _ => {
+                                    lcore_report_error(&err, &mut symbol_table)
+                                }
                             }
-                        },
+                        }
 
                         // NOTE(pebaz): Repr print a non-null value
                         Ok(val) => {
@@ -723,7 +1563,7 @@ pub fn lcore_repl() {
                             } else {
                                 print!("-> ");
                                 lcore_print(
-                                    &mut Value::Array(vec![val]),
+                                    &mut Value::Array(Rc::new(vec![val])),
                                     &mut symbol_table,
                                 )
                                 .ok();
@@ -735,30 +1575,23 @@ pub fn lcore_repl() {
                 }
 
                 Err(err) => {
-                    match err.variant {
-                        ParsingError {
-                            positives,
-                            negatives,
-                        } => {
-                            // NOTE(pebaz): Something has gone wrong and Pest
-                            // cannot parse the input.
-                            if positives.contains(&Rule::EOI) {
-                                println!("Enter only one form per line");
-                                break;
-                            }
-                            // NOTE(pebaz): This is needed to not read
-                            // additional lines from stdin if the user just
-                            // presses the `enter` key.
-                            else if positives.contains(&Rule::Program) {
-                                break;
-                            }
-                            // Add whitespace because lines are concatenated
-                            else {
-                                add_space = true;
-                            }
+                    if let ParsingError { positives, .. } = err.variant {
+                        // NOTE(pebaz): Something has gone wrong and Pest
+                        // cannot parse the input.
+                        if positives.contains(&Rule::EOI) {
+                            println!("Enter only one form per line");
+                            break;
+                        }
+                        // NOTE(pebaz): This is needed to not read
+                        // additional lines from stdin if the user just
+                        // presses the `enter` key.
+                        else if positives.contains(&Rule::Program) {
+                            break;
+                        }
+                        // Add whitespace because lines are concatenated
+                        else {
+                            add_space = true;
                         }
-
-                        _ => (),
                     }
 
                     print!(" > ");
@@ -769,80 +1602,312 @@ pub fn lcore_repl() {
     }
 }
 
-pub fn lcore_import_file(file: String) -> SymTab {
-    let unparsed_file =
-        fs::read_to_string(file).expect("LCORE: Error Reading File");
+/// Run a file in its own namespace for `import`.
+///
+/// Returns: The names the file defined.
+pub fn lcore_import_file(
+    file: String,
+    importer: &Environment,
+) -> Result<SymTab, LCoreError> {
+    let code = lcore_read_file(&file)?;
+
+    let mut symbol_table = importer.share_streams();
+    symbol_table.push();
 
-    // This can be a concurrent task
-    let lines_of_code = count_newlines(unparsed_file.as_str()) + 1;
+    import_builtins(&mut symbol_table);
 
-    let program = LambdaCoreParser::parse(Rule::Program, &unparsed_file)
-        .expect("LCORE: Failed To Parse")
-        .next()
-        .unwrap();
+    lcore_eval(&code, &file, &mut symbol_table)?;
 
-    let mut symbol_table = Environment::new();
-    symbol_table.push();
+    // Return the resulting namespace to be merged with importing module
+    // The importer would then symtab.extend(val);
+    Ok(symbol_table.pop())
+}
 
-    import_builtins(&mut symbol_table);
+/// Read the source code of a file to be run.
+pub fn lcore_read_file(file: &str) -> Result<String, LCoreError> {
+    fs::read_to_string(file).map_err(|err| {
+        LCoreError::IOError(format!("Cannot read file \"{}\": {}", file, err))
+    })
+}
+
+/// Parse and interpret a program in the current scope of `symbol_table`.
+///
+/// `file` names the source of the code in tracebacks and parse errors.
+///
+/// Returns: The value of the last form in the program.
+pub fn lcore_eval(
+    code: &str,
+    file: &str,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let forms = lcore_parse_code(code, file)?;
+
+    lcore_interpret(&forms, symbol_table)
+        .map_err(|err| lcore_top_level_error(err, symbol_table))
+}
+
+/// Turn `ret` and `break` that reached the top level into errors, since they
+/// have nothing to leave there and are mistakes rather than control flow.
+///
+/// Returns: The error to report in place of `err`.
+fn lcore_top_level_error(
+    err: LCoreError,
+    symbol_table: &mut Environment,
+) -> LCoreError {
+    match err {
+        LCoreError::ReturnError | LCoreError::TailCallError => {
+            // NOTE(pebaz): Don't keep the call `ret` would have made alive
+            symbol_table.tail_call = None;
+            LCoreError::LambdaCoreError(
+                "\"ret\" used outside of a function".to_string(),
+            )
+        }
+        LCoreError::BreakError => LCoreError::LambdaCoreError(
+            "\"break\" used outside of a loop".to_string(),
+        ),
+        err => err,
+    }
+}
 
-    let mut stack = VecDeque::with_capacity(lines_of_code);
+/// Print an error that reached the top level along with the calls that led to
+/// it. Nothing is printed for a call to `exit`.
+pub fn lcore_report_error(err: &LCoreError, symbol_table: &mut Environment) {
+    eprint!("{}", lcore_format_error(err, symbol_table));
+}
 
-    let planned = stack.capacity();
-    let loc = lcore_parse(program, &mut stack);
+/// Describe an error along with the calls that led to it, one line each.
+/// A call to `exit` is not described at all.
+pub fn lcore_format_error(
+    err: &LCoreError,
+    symbol_table: &mut Environment,
+) -> String {
+    let traceback = symbol_table.take_traceback();
 
-    if let Err(err) = lcore_interpret(&mut stack, &mut symbol_table) {
-        match err {
-            LCoreError::LambdaCoreError(s) => println!("{}", s),
-            LCoreError::IndexError(s) => println!("{}", s),
-            LCoreError::ArgumentError(s) => println!("{}", s),
-            LCoreError::NameError(s) => println!("{}", s),
+    if let LCoreError::ExitError(_) = err {
+        return String::new();
+    }
 
-            // LCoreError::ReturnError(v) => println!("IMPORT: NOT IMPLEMENTED
-            // ERROR"),
-            LCoreError::ReturnError => {
-                println!("IMPORT: NOT IMPLEMENTED ERROR")
-            }
-            LCoreError::BreakError => println!("IMPORT: NOT IMPLEMENTED!"),
+    let mut report = String::new();
+
+    if !traceback.is_empty() {
+        report.push_str("Traceback (most recent call last):\n");
+        for frame in traceback {
+            report.push_str(&format!("{}\n", frame));
         }
     }
 
-    // Return the resulting namespace to be merged with importing module
-    // The importer would then symtab.extend(val);
-    symbol_table.pop()
+    report.push_str(&format!("{}\n", err));
+
+    if let LCoreError::ParseError(_, Some(location)) = err {
+        report.push_str(&format!("{}\n", location));
+    }
+
+    report
 }
 
-pub fn lcore_execute_string(code: String) {
-    let program = LambdaCoreParser::parse(Rule::Program, &code)
-        .expect("LCORE: Failed To Parse")
-        .next()
-        .unwrap();
+/// Parse a whole program, reporting a syntax error instead of panicking.
+///
+/// `file` names the source of the code in spans and parse errors.
+///
+/// Returns: The forms that make up the program.
+pub fn lcore_parse_code(
+    code: &str,
+    file: &str,
+) -> Result<Vec<Expr>, LCoreError> {
+    let source = Rc::new(Source::new(file, code));
+
+    LambdaCoreParser::parse(Rule::Program, code)
+        .map_err(Box::new)
+        .and_then(|mut pairs| lcore_parse(pairs.next().unwrap(), &source))
+        .map_err(|err| lcore_parse_error(code, file, &err))
+}
 
-    let mut symbol_table = Environment::new();
-    symbol_table.push();
+/// Explain why a program could not be parsed and where.
+///
+/// Unbalanced brackets and strings are found by scanning the source since
+/// Pest only notices them at the end of the file. Anything else is reported
+/// at the position Pest gave up.
+fn lcore_parse_error(code: &str, file: &str, err: &Error<Rule>) -> LCoreError {
+    let (explanation, line, column) = match lcore_find_unbalanced(code) {
+        Some(found) => found,
+        None => {
+            let (line, col) = match err.line_col {
+                LineColLocation::Pos(pos) => pos,
+                LineColLocation::Span(start, _) => start,
+            };
+            let found = code
+                .lines()
+                .nth(line - 1)
+                .and_then(|text| text.chars().nth(col - 1));
+            (lcore_describe_parse_error(err, found), line, col)
+        }
+    };
 
-    import_builtins(&mut symbol_table);
+    let location = ParseLocation {
+        file: file.to_string(),
+        line,
+        column,
+        text: code.lines().nth(line - 1).unwrap_or("").to_string(),
+    };
+
+    LCoreError::ParseError(explanation, Some(Box::new(location)))
+}
+
+/// Find a bracket or string that is never closed, or a closing bracket that
+/// does not match the one before it.
+///
+/// Returns: An explanation of the problem along with its line and column.
+fn lcore_find_unbalanced(code: &str) -> Option<(String, usize, usize)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut open: Vec<(char, usize, usize)> = Vec::new();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+
+    // Move past `count` characters, keeping track of lines and columns
+    let advance = |i: &mut usize, line: &mut usize, col: &mut usize, count| {
+        for _ in 0..count {
+            if chars.get(*i) == Some(&'\n') {
+                *line += 1;
+                *col = 1;
+            } else {
+                *col += 1;
+            }
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        let (start_line, start_col) = (line, col);
+
+        match (chars[i], chars.get(i + 1)) {
+            ('"', _) => {
+                advance(&mut i, &mut line, &mut col, 1);
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Some((
+                                "unterminated string".to_string(),
+                                start_line,
+                                start_col,
+                            ))
+                        }
+                        Some('"') => break,
+                        Some('\\') => advance(&mut i, &mut line, &mut col, 2),
+                        Some(_) => advance(&mut i, &mut line, &mut col, 1),
+                    }
+                }
+            }
 
-    let mut stack = VecDeque::new();
-    let planned = stack.capacity();
-    lcore_parse(program, &mut stack);
-
-    if let Err(err) = lcore_interpret(&mut stack, &mut symbol_table) {
-        match err {
-            LCoreError::LambdaCoreError(s) => println!("{}", s),
-            LCoreError::IndexError(s) => println!("{}", s),
-            LCoreError::ArgumentError(s) => println!("{}", s),
-            LCoreError::NameError(s) => println!("{}", s),
-
-            // LCoreError::ReturnError(v) => println!("EXECUTE_STRING: NOT
-            // IMPLEMENTED ERROR"),
-            LCoreError::ReturnError => {
-                println!("EXECUTE_STRING: NOT IMPLEMENTED ERROR")
+            (':', Some(':')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    advance(&mut i, &mut line, &mut col, 1);
+                }
+                continue;
             }
-            LCoreError::BreakError => {
-                println!("EXECUTE_STRING: NOT IMPLEMENTED!")
+
+            (':', Some('>')) => {
+                advance(&mut i, &mut line, &mut col, 2);
+                while !(chars.get(i) == Some(&'<')
+                    && chars.get(i + 1) == Some(&':'))
+                {
+                    if i >= chars.len() {
+                        return Some((
+                            "unterminated block comment".to_string(),
+                            start_line,
+                            start_col,
+                        ));
+                    }
+                    advance(&mut i, &mut line, &mut col, 1);
+                }
+                advance(&mut i, &mut line, &mut col, 1);
             }
+
+            (c @ '(', _) | (c @ '[', _) => open.push((c, line, col)),
+
+            (c @ ')', _) | (c @ ']', _) => {
+                let expected = if c == ')' { '(' } else { '[' };
+                match open.pop() {
+                    None => {
+                        return Some((
+                            format!(
+                                "unexpected `{}` with nothing to close",
+                                c
+                            ),
+                            line,
+                            col,
+                        ))
+                    }
+                    Some((opener, l, co)) if opener != expected => {
+                        return Some((
+                            format!(
+                                "mismatched `{}`: the `{}` opened at line {}, \
+                                 column {} is still open",
+                                c, opener, l, co
+                            ),
+                            line,
+                            col,
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+
+            _ => (),
+        }
+
+        advance(&mut i, &mut line, &mut col, 1);
+    }
+
+    open.pop().map(|(opener, line, col)| {
+        (
+            format!(
+                "unclosed `{}` opened at line {}, column {}",
+                opener, line, col
+            ),
+            line,
+            col,
+        )
+    })
+}
+
+/// Turn the rules Pest expected into something a person can read.
+fn lcore_describe_parse_error(
+    err: &Error<Rule>,
+    found: Option<char>,
+) -> String {
+    let found = match found {
+        Some(c) => format!("unexpected `{}`", c),
+        None => "unexpected end of line".to_string(),
+    };
+
+    let positives = match &err.variant {
+        ParsingError { positives, .. } => positives,
+        CustomError { message } => return message.clone(),
+    };
+
+    let mut expected: Vec<&str> = Vec::new();
+    for rule in positives {
+        let description = match rule {
+            Rule::EOI => "the end of the file",
+            Rule::Identifier => "a name",
+            Rule::Function => "a call",
+            Rule::Quote | Rule::BackTick => "a quoted value",
+            Rule::String
+            | Rule::Number
+            | Rule::Array
+            | Rule::Boolean
+            | Rule::Null => "a value",
+            _ => continue,
+        };
+        if !expected.contains(&description) {
+            expected.push(description);
+        }
+    }
+
+    match expected.split_last() {
+        None => found,
+        Some((last, [])) => format!("{}, expected {}", found, last),
+        Some((last, rest)) => {
+            format!("{}, expected {} or {}", found, rest.join(", "), last)
         }
     }
-    symbol_table.pop();
 }
@@ -0,0 +1,31 @@
+#![allow(non_snake_case)]
+
+//! LambdaCore, a Lisp dialect written in Rust.
+//!
+//! Programs can be run from other Rust code with an `Interpreter`:
+//!
+//! ```
+//! use lambda_core::{Interpreter, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.eval_str("(defn 'double ['x] '[(ret (* x 2))])").unwrap();
+//!
+//! let result = interpreter.call_function("double", vec![Value::Int(21)]);
+//! assert_eq!(result.unwrap(), Value::Int(42));
+//! ```
+
+pub mod ast;
+pub mod builtin;
+pub mod compiler;
+pub mod convert;
+pub mod interpreter;
+pub mod lcore;
+pub mod symbol;
+pub mod vm;
+
+pub use crate::ast::Expr;
+pub use crate::convert::{FromValue, IntoValue};
+pub use crate::interpreter::Interpreter;
+pub use lambda_core_derive::{FromValue, IntoValue};
+pub use crate::lcore::{Environment, LCoreError, NativeFunc, Value};
+pub use crate::symbol::Symbol;
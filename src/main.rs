@@ -1,30 +1,20 @@
-#![cfg_attr(debug_assertions, allow(unused_imports))]
-#![cfg_attr(debug_assertions, allow(dead_code))]
-#![cfg_attr(debug_assertions, allow(unused_variables))]
-
-#![allow(non_snake_case)]
-
-
 #[macro_use]
 extern crate clap;
 
-mod builtin;
-mod lcore;
-
-use crate::builtin::*;
-use crate::lcore::pest::Parser;
-use crate::lcore::*;
 use clap::{App, Arg};
 use colored::*;
-use std::cmp::min;
-use std::collections::{HashMap, VecDeque};
-use std::env;
-use std::fmt;
-use std::fs;
-use std::iter::FromIterator;
-use std::str::FromStr;
+use lambda_core::lcore::{lcore_repl, ParseLocation};
+use lambda_core::{Interpreter, LCoreError};
+use std::io::IsTerminal;
 
 fn main() {
+    std::process::exit(run());
+}
+
+/// Run the program given on the command line.
+///
+/// Returns: The status the process should exit with.
+fn run() -> i32 {
     let matches = App::new("LambdaCore")
         .version(crate_version!())
         .author(crate_authors!())
@@ -49,15 +39,69 @@ fn main() {
 
     // Get other CLI switches (not FILE yet)
 
+    // NOTE(pebaz): Only highlight diagnostics for a person at a terminal
+    if !std::io::stderr().is_terminal() {
+        colored::control::set_override(false);
+    }
+
     let code_str = matches.value_of("code");
     let code_file = matches.value_of("file");
 
-    match (code_file, code_str) {
-        (None, None) => lcore_repl(),
-        (None, Some(code)) => lcore_execute_string(code.to_string()),
-        (Some(file), None) => {
-            let _ = lcore_import_file(file.to_string());
+    let mut interpreter = Interpreter::new();
+
+    let result = match (code_file, code_str) {
+        (None, None) => {
+            lcore_repl();
+            return 0;
+        }
+        (None, Some(code)) => interpreter.eval_str(code),
+        (Some(file), None) => interpreter.eval_file(file),
+        _ => return 0,
+    };
+
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            report_error(&mut interpreter, &err);
+            err.exit_status()
         }
-        _ => (),
     }
 }
+
+/// Print an error the way `Interpreter::report_error` does, highlighting the
+/// line a program could not be parsed at.
+fn report_error(interpreter: &mut Interpreter, err: &LCoreError) {
+    match err {
+        LCoreError::ParseError(explanation, Some(location)) => {
+            let error = LCoreError::ParseError(explanation.clone(), None);
+            eprint!("{}", interpreter.format_error(&error));
+            eprintln!("{}", highlight(location));
+        }
+        _ => interpreter.report_error(err),
+    }
+}
+
+/// Show the line a program could not be parsed at with a caret under the
+/// column, like `ParseLocation` does but in colour.
+fn highlight(location: &ParseLocation) -> String {
+    let gutter = location.gutter();
+    let bar = "|".blue().bold();
+
+    format!(
+        "{}{} {}:{}:{}\n{} {}\n{} {} {}\n{} {} {}{}",
+        gutter,
+        "-->".blue().bold(),
+        location.file,
+        location.line,
+        location.column,
+        gutter,
+        bar,
+        location.line.to_string().blue().bold(),
+        bar,
+        location.text,
+        gutter,
+        bar,
+        location.indent(),
+        "^".red().bold()
+    )
+}
@@ -0,0 +1,115 @@
+//! Names interned to small integers, so that looking up a variable compares
+//! and hashes a number rather than its text.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::rc::Rc;
+
+/// An interned name. Two symbols are equal exactly when their names are.
+///
+/// Symbols are interned per thread, just like the interpreter itself is
+/// kept to one thread.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// The symbol for a name, making a new one the first time it is seen.
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.symbols.get(name) {
+                return symbol;
+            }
+
+            // NOTE(pebaz): Names are only ever added, so only names from
+            // source code should be interned. Looking a name up by text
+            // should use `Symbol::lookup` so that it is not kept.
+            let name: Rc<str> = Rc::from(name);
+            let symbol = Symbol(interner.names.len() as u32);
+            interner.names.push(name.clone());
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// The symbol for a name if it has been interned, without interning it.
+    /// A name that was never interned cannot have a variable bound to it.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        INTERNER.with(|interner| interner.borrow().symbols.get(name).copied())
+    }
+
+    /// The name the symbol was interned from.
+    pub fn name(self) -> Rc<str> {
+        INTERNER
+            .with(|interner| interner.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        write!(fm, "{}", self.name())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, fm: &mut fmt::Formatter) -> fmt::Result {
+        write!(fm, "{}", self.name())
+    }
+}
+
+/// A map keyed by symbols. Symbols are already small and unique, so they are
+/// hashed with a single multiplication rather than with SipHash.
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(self.0.rotate_left(8) ^ byte as u64);
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
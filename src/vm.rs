@@ -0,0 +1,250 @@
+//! Runs the bytecode made by `compiler`.
+
+use crate::builtin::*;
+use crate::compiler::*;
+use crate::lcore::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A `loop` that is being run.
+struct Loop {
+    count: i64,
+    limit: i64,
+
+    /// Where the `ExitLoop` op is.
+    exit: usize,
+
+    /// The height of the stack when the loop started.
+    height: usize,
+
+    /// The scope to return to after the loop.
+    scope: Rc<RefCell<Scope>>,
+}
+
+struct Machine {
+    ip: usize,
+    stack: Vec<Value>,
+    loops: Vec<Loop>,
+}
+
+/// Run compiled code in the current scope of `symbol_table`.
+///
+/// Returns: The value the code leaves on the stack, or the value returned
+/// with `ret` for the body of a function.
+pub fn lcore_run(
+    chunk: &Chunk,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    // NOTE(pebaz): Function calls and blocks run by builtins recurse on the
+    // Rust stack, so it is grown on the heap as needed. This way only
+    // `LCORE_MAX_DEPTH` limits recursion, whatever thread the code runs on.
+    stacker::maybe_grow(LCORE_STACK_RED_ZONE, LCORE_STACK_SEGMENT, || {
+        lcore_run_chunk(chunk, symbol_table)
+    })
+}
+
+fn lcore_run_chunk(
+    chunk: &Chunk,
+    symbol_table: &mut Environment,
+) -> Result<Value, LCoreError> {
+    let mut machine = Machine {
+        ip: 0,
+        stack: Vec::new(),
+        loops: Vec::new(),
+    };
+
+    loop {
+        match machine.execute(chunk, symbol_table) {
+            Ok(value) => return Ok(value),
+
+            // NOTE(pebaz): `break` can come from a block run by a builtin or
+            // from a function called in the loop, so it is caught here.
+            Err(LCoreError::BreakError) if !machine.loops.is_empty() => {
+                let done = machine.loops.pop().unwrap();
+                machine.stack.truncate(done.height);
+                machine.stack.push(Value::Null);
+                machine.ip = done.exit + 1;
+                symbol_table.restore(done.scope);
+            }
+
+            Err(err) => {
+                // NOTE(pebaz): Leave any loops before unwinding so that an
+                // error caught by `try` does not leave their scopes behind.
+                if let Some(outer) = machine.loops.first() {
+                    symbol_table.restore(outer.scope.clone());
+                }
+
+                if !err.is_control_flow() {
+                    symbol_table.trace_error(chunk.span(machine.ip));
+                }
+
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Machine {
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    /// Run ops until the chunk is done or one of them fails, leaving `ip` at
+    /// the op that failed.
+    fn execute(
+        &mut self,
+        chunk: &Chunk,
+        symbol_table: &mut Environment,
+    ) -> Result<Value, LCoreError> {
+        while let Some(op) = chunk.ops.get(self.ip) {
+            match *op {
+                Op::Const(i) => self.stack.push(chunk.constants[i].clone()),
+                Op::Int(i) => self.stack.push(Value::Int(i)),
+                Op::Null => self.stack.push(Value::Null),
+
+                Op::Pop => {
+                    self.stack.pop();
+                }
+
+                Op::Drop(count) => {
+                    self.stack.truncate(self.stack.len() - count);
+                }
+
+                Op::GetName(i) => {
+                    let value = symbol_table.lookup(chunk.names[i])?;
+                    self.stack.push(value);
+                }
+
+                Op::GetLocal { depth, slot } => {
+                    let value = symbol_table.get_local(depth, slot)?;
+                    self.stack.push(value);
+                }
+
+                Op::SetName(i) => {
+                    let value = self.pop();
+                    symbol_table.insert(chunk.names[i], value);
+                    self.stack.push(Value::Null);
+                }
+
+                Op::SetLocal { depth, slot } => {
+                    let value = self.pop();
+                    symbol_table.set_local(depth, slot, value);
+                    self.stack.push(Value::Null);
+                }
+
+                Op::Macro { args, end } => {
+                    if let Some(Value::Macro(_)) = self.stack.last() {
+                        let mac = match self.pop() {
+                            Value::Macro(mac) => mac,
+                            _ => unreachable!(),
+                        };
+                        let (span, items) = &chunk.arguments[args];
+                        let args = lcore_macro_arguments(&items[1..]);
+                        let value =
+                            lcore_call_macro(mac, args, span, symbol_table)?;
+                        self.stack.push(value);
+                        self.ip = end;
+                        continue;
+                    }
+                }
+
+                Op::Call(count) => self.call(count, symbol_table, false)?,
+                Op::TailCall(count) => self.call(count, symbol_table, true)?,
+
+                Op::Array(count) => {
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Value::Array(Rc::new(items)));
+                }
+
+                Op::QuasiQuote(i) => {
+                    let template = &chunk.templates[i];
+                    let value = lcore_quasi_quote(template, symbol_table)?;
+                    self.stack.push(value);
+                }
+
+                Op::Jump(to) => {
+                    self.ip = to;
+                    continue;
+                }
+
+                Op::Branch(to) => {
+                    let condition = self.pop();
+                    if !lcore_expect_bool(&condition, "if")? {
+                        self.ip = to;
+                        continue;
+                    }
+                }
+
+                Op::SelTest { value, count, next } => {
+                    let values = &self.stack[self.stack.len() - count - 1..];
+                    let (compare, value) = (&values[0], &values[value + 1]);
+                    if !lcore_sel_matches(compare, value, symbol_table) {
+                        self.ip = next;
+                        continue;
+                    }
+                }
+
+                Op::EnterLoop { scope, exit } => {
+                    let iters = self.pop();
+                    let limit = lcore_expect_int(&iters, "loop")?;
+
+                    self.loops.push(Loop {
+                        count: 0,
+                        limit,
+                        exit,
+                        height: self.stack.len(),
+                        scope: symbol_table.capture(),
+                    });
+                    symbol_table.push_slots(chunk.scopes[scope].clone());
+                }
+
+                Op::NextLoop(exit) => {
+                    let current = self.loops.last_mut().unwrap();
+                    if current.count >= current.limit {
+                        self.ip = exit;
+                        continue;
+                    }
+
+                    let i = current.count;
+                    current.count += 1;
+                    symbol_table.set_local(0, 0, Value::Int(i));
+                }
+
+                Op::ExitLoop => {
+                    let done = self.loops.pop().unwrap();
+                    symbol_table.restore(done.scope);
+                    self.stack.push(Value::Null);
+                }
+
+                Op::Lambda(i) => {
+                    let function = &chunk.functions[i];
+                    self.stack.push(Value::Lambda(Rc::new(Lambda {
+                        name: function.name.clone(),
+                        code: function.clone(),
+                        env: symbol_table.capture(),
+                    })));
+                }
+
+                Op::Return => return Ok(self.pop()),
+            }
+
+            self.ip += 1;
+        }
+
+        Ok(self.stack.pop().unwrap_or(Value::Null))
+    }
+
+    fn call(
+        &mut self,
+        count: usize,
+        symbol_table: &mut Environment,
+        tail: bool,
+    ) -> Result<(), LCoreError> {
+        let args = self.stack.split_off(self.stack.len() - count);
+        let func = self.pop();
+        let args = Value::Array(Rc::new(args));
+        let value = lcore_call_tail(func, args, symbol_table, tail)?;
+        self.stack.push(value);
+        Ok(())
+    }
+}
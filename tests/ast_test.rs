@@ -0,0 +1,138 @@
+use lambda_core::lcore::{lcore_parse_code, Source, Span};
+use lambda_core::{Expr, LCoreError, Symbol, Value};
+use std::rc::Rc;
+
+/// A span for building forms to compare with, since where a form was written
+/// does not affect equality.
+fn nowhere() -> Span {
+    Rc::new(Source::new("<test>", "")).span(0, 0)
+}
+
+fn symbol(name: &str) -> Expr {
+    Expr::Symbol(nowhere(), Symbol::intern(name))
+}
+
+fn parse(code: &str) -> Vec<Expr> {
+    lcore_parse_code(code, "<test>").unwrap()
+}
+
+#[test]
+fn test_parse_tree() {
+    let forms = parse("(set 'x [1 \"a\" y]) :: comment\n(print x)");
+    assert_eq!(forms.len(), 2);
+
+    let items = match &forms[0] {
+        Expr::Call(span, items) => {
+            assert_eq!(span.line_col(), (1, 1));
+            items
+        }
+        other => panic!("Expected a call, got {:?}", other),
+    };
+
+    assert_eq!(items[0], symbol("set"));
+    assert_eq!(items[1], Expr::Quote(nowhere(), Rc::new(symbol("x"))));
+    assert_eq!(
+        items[2],
+        Expr::Array(
+            nowhere(),
+            vec![
+                Expr::Literal(nowhere(), Value::Int(1)),
+                Expr::Literal(
+                    nowhere(),
+                    Value::String(Rc::new("a".to_string()))
+                ),
+                symbol("y"),
+            ]
+        )
+    );
+
+    match &forms[1] {
+        Expr::Call(span, _) => assert_eq!(span.line_col(), (2, 1)),
+        other => panic!("Expected a call, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_quotes() {
+    // A quoted call is a block of code holding just that call
+    match &parse("'(f x)")[0] {
+        Expr::Quote(_, quoted) => match &**quoted {
+            Expr::Array(_, forms) => {
+                assert!(matches!(forms.as_slice(), [Expr::Call(..)]))
+            }
+            other => panic!("Expected a block, got {:?}", other),
+        },
+        other => panic!("Expected a quote, got {:?}", other),
+    }
+
+    let template = match &parse("`[a ,b ,@c]")[0] {
+        Expr::QuasiQuote(_, template) => template.clone(),
+        other => panic!("Expected a quasi-quote, got {:?}", other),
+    };
+    assert_eq!(
+        *template,
+        Expr::Array(
+            nowhere(),
+            vec![
+                symbol("a"),
+                Expr::Unquote(nowhere(), Box::new(symbol("b"))),
+                Expr::Splice(nowhere(), Box::new(symbol("c"))),
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_parse_spans() {
+    // Every form knows the text it was parsed from
+    let code = "(print x 1.5\n  'y [\"a\" Null] `[,z ,@w])";
+    let forms = parse(code);
+
+    let text = |form: &Expr| {
+        let span = form.span();
+        assert_eq!(span.file(), "<test>");
+        &code[span.start()..span.end()]
+    };
+
+    let items = match &forms[0] {
+        Expr::Call(_, items) => items.clone(),
+        other => panic!("Expected a call, got {:?}", other),
+    };
+    assert_eq!(text(&forms[0]), code);
+    assert_eq!(text(&items[0]), "print");
+    assert_eq!(text(&items[1]), "x");
+    assert_eq!(text(&items[2]), "1.5");
+    assert_eq!(text(&items[3]), "'y");
+    assert_eq!(items[3].span().line_col(), (2, 3));
+    assert_eq!(text(&items[4]), "[\"a\" Null]");
+    assert_eq!(text(&items[5]), "`[,z ,@w]");
+
+    match &items[4] {
+        Expr::Array(_, values) => {
+            assert_eq!(text(&values[0]), "\"a\"");
+            assert_eq!(text(&values[1]), "Null");
+        }
+        other => panic!("Expected an array, got {:?}", other),
+    }
+
+    match &items[5] {
+        Expr::QuasiQuote(_, template) => match &**template {
+            Expr::Array(_, parts) => {
+                assert_eq!(text(&parts[0]), ",z");
+                assert_eq!(text(&parts[1]), ",@w");
+            }
+            other => panic!("Expected an array, got {:?}", other),
+        },
+        other => panic!("Expected a quasi-quote, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_stray_unquote() {
+    match lcore_parse_code("(print ,x)", "<test>") {
+        Err(LCoreError::ParseError(message, _)) => {
+            assert!(message.contains("unquote used outside of a quasi-quote"))
+        }
+        other => panic!("Expected a ParseError, got {:?}", other),
+    }
+}
@@ -0,0 +1,153 @@
+use indexmap::IndexMap;
+use lambda_core::convert::lcore_into_struct;
+use lambda_core::{FromValue, Interpreter, IntoValue, NativeFunc, Value};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, FromValue, IntoValue)]
+struct Point {
+    x: i64,
+    y: i64,
+    label: Option<String>,
+}
+
+#[test]
+fn test_primitives() {
+    assert_eq!(5u8.into_value(), Value::Int(5));
+    assert_eq!(i32::from_value(Value::Int(-3)).unwrap(), -3);
+    assert_eq!(f64::from_value(Value::Int(2)).unwrap(), 2.0);
+    assert!(bool::from_value(Value::Boolean(true)).unwrap());
+    assert_eq!("hi".into_value(), Value::String(Rc::new("hi".to_string())));
+
+    let err = u8::from_value(Value::Int(300)).unwrap_err();
+    assert_eq!(err.message(), "Int 300 is out of range for u8");
+
+    let err = String::from_value(Value::Int(1)).unwrap_err();
+    assert_eq!(err.kind(), "TypeError");
+    assert_eq!(err.message(), "Cannot convert Int to a String");
+}
+
+#[test]
+fn test_containers() {
+    let value = vec![Some(1), None].into_value();
+    let expect = Value::Array(Rc::new(vec![Value::Int(1), Value::Null]));
+    assert_eq!(value, expect);
+    assert_eq!(
+        Vec::<Option<i64>>::from_value(value).unwrap(),
+        vec![Some(1), None]
+    );
+
+    let mut scores = HashMap::new();
+    scores.insert("a".to_string(), 1.5);
+    let value = scores.clone().into_value();
+    assert_eq!(HashMap::<String, f64>::from_value(value).unwrap(), scores);
+
+    let mut order = IndexMap::new();
+    order.insert("b".to_string(), 1);
+    order.insert("a".to_string(), 2);
+    let value = order.clone().into_value();
+    let keys: Vec<String> = IndexMap::<String, i64>::from_value(value)
+        .unwrap()
+        .into_keys()
+        .collect();
+    assert_eq!(keys, vec!["b", "a"]);
+
+    let value = (1, "two").into_value();
+    let (one, two) = <(u8, String)>::from_value(value).unwrap();
+    assert_eq!((one, two.as_str()), (1, "two"));
+
+    let err = Vec::<i64>::from_value(Value::Array(Rc::new(vec![Value::Null])));
+    assert_eq!(err.unwrap_err().message(), "Cannot convert Null to an Int");
+}
+
+#[test]
+fn test_derive() {
+    let point = Point {
+        x: 1,
+        y: 2,
+        label: None,
+    };
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("p", point.into_value());
+
+    let result = interpreter.eval_str("(+ (get p \"x\") (get p \"y\"))");
+    assert_eq!(result.unwrap(), Value::Int(3));
+
+    // Missing optional fields are left out
+    let value = interpreter.eval_str("(dict 'x 3 'y 4)").unwrap();
+    let expect = Point {
+        x: 3,
+        y: 4,
+        label: None,
+    };
+    assert_eq!(Point::from_value(value).unwrap(), expect);
+
+    let value = interpreter.eval_str("(dict 'x 3)").unwrap();
+    let err = Point::from_value(value).unwrap_err();
+    assert_eq!(err.kind(), "KeyError");
+    assert_eq!(err.message(), "Missing field \"y\" for Point");
+}
+
+#[test]
+fn test_derive_struct() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("(type 'point '[x y label])").unwrap();
+
+    let kind = match interpreter.get_global("point") {
+        Some(Value::Type(kind)) => kind,
+        other => panic!("Expected a Type, got {:?}", other),
+    };
+
+    let point = Point {
+        x: 5,
+        y: 6,
+        label: Some("corner".to_string()),
+    };
+    interpreter.set_global("p", lcore_into_struct(point, &kind).unwrap());
+
+    let result = interpreter.eval_str("(get p 'label)").unwrap();
+    assert_eq!(result, Value::String(Rc::new("corner".to_string())));
+
+    let value = interpreter.eval_str("(new point 7 8 Null)").unwrap();
+    let expect = Point {
+        x: 7,
+        y: 8,
+        label: None,
+    };
+    assert_eq!(Point::from_value(value).unwrap(), expect);
+}
+
+#[test]
+fn test_typed_builtin() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_function(
+        NativeFunc::new("scale", |args, _| {
+            let (points, factor) =
+                <(Vec<Point>, i64)>::from_value(args.clone())?;
+
+            let scaled: Vec<Point> = points
+                .into_iter()
+                .map(|p| Point {
+                    x: p.x * factor,
+                    y: p.y * factor,
+                    label: p.label,
+                })
+                .collect();
+
+            Ok(scaled.into_value())
+        })
+        .with_arity(2, 2),
+    );
+
+    let result = interpreter
+        .eval_str("(get (get (scale [(dict 'x 1 'y 2)] 10) 0) \"y\")")
+        .unwrap();
+    assert_eq!(result, Value::Int(20));
+
+    let err = interpreter.eval_str("(scale [5] 10)").unwrap_err();
+    assert_eq!(
+        err.message(),
+        "Cannot convert Int to a Dict or Struct for Point"
+    );
+}
@@ -1,20 +1,63 @@
+use lambda_core::{Interpreter, LCoreError, Value};
+use std::cell::RefCell;
 use std::process::Command;
+use std::rc::Rc;
 
+/// What a program printed and the status the binary would exit with.
+struct Run {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
 
 fn run_file(file: String) -> String {
-    let target = if cfg!(debug_assertions) {
-        "target/debug/lambda_core"
-    } else {
-        "target/release/lambda_core"
-    };
+    let output = run_failing_file(&file);
+    assert_eq!(output.status, 0, "{} exited with an error", file);
+    output.stdout
+}
 
-    let output = Command::new(target)
-        .arg("-f")
-        .arg(file)
-        .output()
+fn run_failing_file(file: &str) -> Run {
+    let file = file.to_string();
+    run(move |interpreter| interpreter.eval_file(&file))
+}
+
+fn run_code(code: &str) -> Run {
+    let code = code.to_string();
+    run(move |interpreter| interpreter.eval_str(&code))
+}
+
+/// Run a program in-process the way the binary would, capturing its output.
+fn run<F>(program: F) -> Run
+where
+    F: FnOnce(&mut Interpreter) -> Result<Value, LCoreError> + Send + 'static,
+{
+    let thread = std::thread::Builder::new()
+        .spawn(move || {
+            let stdout = Rc::new(RefCell::new(Vec::new()));
+            let mut interpreter = Interpreter::new();
+            interpreter.set_output(stdout.clone());
+
+            let (stderr, status) = match program(&mut interpreter) {
+                Ok(_) => (String::new(), 0),
+                Err(err) => {
+                    (interpreter.format_error(&err), err.exit_status())
+                }
+            };
+
+            let stdout = String::from_utf8(stdout.borrow().clone()).unwrap();
+            Run {
+                stdout,
+                stderr,
+                status,
+            }
+        })
         .unwrap();
 
-    String::from_utf8(output.stdout).unwrap()
+    thread.join().unwrap_or_else(|_| Run {
+        stdout: String::new(),
+        stderr: "The interpreter panicked".to_string(),
+        status: 101,
+    })
 }
 
 #[test]
@@ -29,6 +72,63 @@ fn test_add() {
     assert_eq!(stdout, expect);
 }
 
+#[test]
+fn test_array() {
+    let stdout = run_file("examples/array.lcore".to_string());
+    let expect = "[1 2 3 4 5]\n\
+                  [1 2]\n\
+                  [1 \"one\" 2 3]\n\
+                  [1 2 3 \"end\"]\n\
+                  [1 2]\n\
+                  [3 2 1]\n\
+                  [1 2 3]\n\
+                  4\n\
+                  1\n\
+                  [3 2 0]\n\
+                  [2 3]\n\
+                  [4 5]\n\
+                  []\n\
+                  1\n\
+                  Null\n\
+                  True\n\
+                  1\n\
+                  [2 3]\n\
+                  Null\n\
+                  [1 2 3 4 5]\n\
+                  [[1 \"a\"] [2 \"b\"]]\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+
+    let output = run_code("(set 'a []) (pop! 'a)");
+    assert!(output
+        .stderr
+        .ends_with("IndexError: \"pop!\" got an empty array\n"));
+
+    let output = run_code("(insert [1] 3 0)");
+    assert!(output
+        .stderr
+        .ends_with("IndexError: Index out of bounds: got 3 but len is 1\n"));
+
+    // Negative indexes past the start are errors rather than wrapping around
+    let output = run_code("(insert [1 2 3] -10 9)");
+    assert!(output
+        .stderr
+        .ends_with("IndexError: Index out of bounds: got -10 but len is 3\n"));
+
+    let output = run_code("(remove [1 2 3] -10)");
+    assert!(output
+        .stderr
+        .ends_with("IndexError: Index out of bounds: got -10 but len is 3\n"));
+
+    let output = run_code("(set 'a [1 2 3]) (remove! 'a -4)");
+    assert!(output
+        .stderr
+        .ends_with("IndexError: Index out of bounds: got -4 but len is 3\n"));
+
+    let output = run_code("(set 'a [1 2 3]) (insert! 'a -4 0) (print a)");
+    assert_eq!(output.stdout, "[0 1 2 3]\n");
+}
+
 #[test]
 fn test_break() {
     let stdout = run_file("examples/break.lcore".to_string());
@@ -48,6 +148,43 @@ fn test_break() {
     assert_eq!(stdout, expect);
 }
 
+#[test]
+fn test_cli() {
+    // The binary reports errors on stderr and exits with their status
+    let target = env!("CARGO_BIN_EXE_lambda_core");
+
+    let output = Command::new(target)
+        .arg("-f")
+        .arg("examples/traceback.lcore")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Traceback (most recent call last):\n"));
+    assert!(stderr.ends_with("ZeroDivisionError: Division by zero\n"));
+
+    let output = Command::new(target).arg("-c").arg("(exit 3)").output();
+    assert_eq!(output.unwrap().status.code(), Some(3));
+
+    let output = Command::new(target).arg("-c").arg("(print (").output();
+    assert_eq!(output.unwrap().status.code(), Some(2));
+}
+
+#[test]
+fn test_closure() {
+    let stdout = run_file("examples/closure.lcore".to_string());
+    let expect = "3\n\
+                  1\n\
+                  42\n\
+                  15\n\
+                  global x\n\
+                  15\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+}
+
 #[test]
 fn test_comment() {
     let stdout = run_file("examples/comment.lcore".to_string());
@@ -88,27 +225,7 @@ fn test_compare() {
 #[test]
 fn test_dict() {
     let stdout = run_file("examples/dict.lcore".to_string());
-    let expect1 = "{ \"first name\": \"David\", \"last name\": \"Wallace\", \"age\": 41 }\n\
-        David\n\
-        41\n\
-        41\n\
-        David\n\
-        Michael\n\
-        True Value\n\
-        Int Value\n\
-        Float Value\n".to_string();
-
-    let expect2 = "{ \"last name\": \"Wallace\", \"first name\": \"David\", \"age\": 41 }\n\
-        David\n\
-        41\n\
-        41\n\
-        David\n\
-        Michael\n\
-        True Value\n\
-        Int Value\n\
-        Float Value\n".to_string();
-
-    let expect3 = "{ \"age\": 41, \"last name\": \"Wallace\", \"first name\": \"David\" }\n\
+    let expect = "{ \"first name\": \"David\", \"last name\": \"Wallace\", \"age\": 41 }\n\
         David\n\
         41\n\
         41\n\
@@ -117,55 +234,67 @@ fn test_dict() {
         True Value\n\
         Int Value\n\
         Float Value\n".to_string();
+    assert_eq!(stdout, expect);
 
-    let expect4 = "{ \"age\": 41, \"first name\": \"David\", \"last name\": \"Wallace\" }\n\
-        David\n\
-        41\n\
-        41\n\
-        David\n\
-        Michael\n\
-        True Value\n\
-        Int Value\n\
-        Float Value\n".to_string();
+    // A key set again keeps its place
+    let output = run_code("(print (dict 'b 1 'a 2 'b 3))");
+    assert_eq!(output.stdout, "{ \"b\": 3, \"a\": 2 }\n");
+}
 
-    let expect5 = "{ \"first name\": \"David\", \"age\": 41, \"last name\": \"Wallace\" }\n\
-        David\n\
-        41\n\
-        41\n\
-        David\n\
-        Michael\n\
-        True Value\n\
-        Int Value\n\
-        Float Value\n".to_string();
+#[test]
+fn test_dict_ops() {
+    let stdout = run_file("examples/dict-ops.lcore".to_string());
+    let expect = "[\"name\" \"age\" \"job\"]\n\
+        [1 2]\n\
+        [[\"title\" \"Manager\"]]\n\
+        True\n\
+        True\n\
+        False\n\
+        0\n\
+        { \"name\": \"David\", \"age\": 42, \"job\": { \"title\": \"Manager\", \"branch\": { \"city\": \"Scranton\" } } }\n\
+        42\n\
+        [\"name\" \"job\" \"age\"]\n\
+        { \"color\": \"blue\", \"size\": 12, \"shape\": \"round\" }\n\
+        { \"color\": \"blue\", \"size\": 10 }\n".to_string();
+    assert_eq!(stdout, expect);
 
-    let expect6 = "{ \"last name\": \"Wallace\", \"age\": 41, \"first name\": \"David\" }\n\
-        David\n\
-        41\n\
-        41\n\
-        David\n\
-        Michael\n\
-        True Value\n\
-        Int Value\n\
-        Float Value\n".to_string();
+    let output = run_code("(set 'd (dict 'a 1)) (del 'd 'b)");
+    assert!(output.stderr.ends_with("KeyError: No key named: \"b\"\n"));
 
-    assert!(
-        (stdout == expect1)
-            | (stdout == expect2)
-            | (stdout == expect3)
-            | (stdout == expect4)
-            | (stdout == expect5)
-            | (stdout == expect6)
-    );
+    let output = run_code("(merge (dict) [1])");
+    assert!(output
+        .stderr
+        .ends_with("TypeError: \"merge\" expects a Dict but got Array\n"));
 }
 
 #[test]
 fn test_error() {
-    let stdout = run_file("examples/error.lcore".to_string());
-    assert_eq!(
-        stdout,
-        "ArgumentError: Odd number of arguments passed to \"dict\"\n"
-            .to_string()
-    );
+    let output = run_failing_file("examples/error.lcore");
+    assert_eq!(output.status, 1);
+    assert!(output.stdout.is_empty());
+
+    let stderr = output.stderr;
+    let expect = "Traceback (most recent call last):\n  \
+                  File \"examples/error.lcore\", line 3, column 1, in <module>\n\
+                  ArgumentError: Odd number of arguments passed to \"dict\"\n";
+    assert_eq!(stderr, expect.to_string());
+}
+
+#[test]
+fn test_error_kinds() {
+    let stdout = run_file("examples/error-kinds.lcore".to_string());
+    let expect = "TypeError\n\
+                  KeyError\n\
+                  ZeroDivisionError\n\
+                  ArityError\n\
+                  IndexError\n\
+                  NameError\n\
+                  ImportError\n\
+                  TypeError\n\
+                  CustomError\n\
+                  No error\n\
+                  KeyError\n";
+    assert_eq!(stdout, expect.to_string());
 }
 
 #[test]
@@ -178,6 +307,19 @@ fn test_eval() {
     assert_eq!(stdout, expect);
 }
 
+#[test]
+fn test_exit() {
+    let output = run_failing_file("examples/exit.lcore");
+    assert_eq!(output.status, 3);
+    assert!(output.stderr.is_empty());
+
+    let stdout = output.stdout;
+    assert_eq!(stdout, "Checked\nCleaning up\n");
+
+    assert_eq!(run_code("(print 1) (quit)").status, 0);
+    assert_eq!(run_code("(exit \"a\")").status, 1);
+}
+
 #[test]
 fn test_fib() {
     let stdout = run_file("examples/fib.lcore".to_string());
@@ -209,6 +351,44 @@ fn test_hello_world() {
     assert_eq!(stdout, "Hello World!\n".to_string());
 }
 
+#[test]
+fn test_higher_order() {
+    let stdout = run_file("examples/higher-order.lcore".to_string());
+    let expect = "[1 4 9]\n\
+                  [1 2 3]\n\
+                  [2 4 6]\n\
+                  10\n\
+                  [10 20 30]\n\
+                  True\n\
+                  False\n\
+                  4\n\
+                  Null\n\
+                  [1 2 3]\n\
+                  [\"a\" \"bb\" \"c\"]\n\
+                  [-0.5 1 1.5 2]\n\
+                  [\"Pam\" \"Kevin\" \"Jim\"]\n\
+                  3\n\
+                  81\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+
+    let output = run_code("(filter len [[1]])");
+    assert!(output
+        .stderr
+        .ends_with("TypeError: \"filter\" expects a Boolean but got Int\n"));
+
+    let output = run_code("(sort [1 \"a\"])");
+    assert!(output
+        .stderr
+        .ends_with("TypeError: Cannot sort String and Int together\n"));
+
+    let output = run_code("(defn 'bad ['x] '[(ret (/ x 0))]) (map bad [1])");
+    assert!(output.stderr.contains("in bad\n"));
+    assert!(output
+        .stderr
+        .ends_with("ZeroDivisionError: Division by zero\n"));
+}
+
 #[test]
 fn test_if() {
     let stdout = run_file("examples/if.lcore".to_string());
@@ -229,10 +409,34 @@ fn test_import() {
     assert_eq!(stdout, expect);
 }
 
+#[test]
+fn test_lambda() {
+    let stdout = run_file("examples/lambda.lcore".to_string());
+    let expect = "16\n\
+                  <Lambda>\n\
+                  81\n\
+                  Hey!!\n\
+                  42\n\
+                  <Lambda>\n\
+                  5\n\
+                  2\n\
+                  True\n\
+                  False\n\
+                  [1 2]\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+}
+
 #[test]
 fn test_len() {
     let stdout = run_file("examples/len.lcore".to_string());
     assert_eq!(stdout, "3\n".to_string());
+
+    // The length of a function or macro is how many parameters it takes
+    let output = run_code("(print (len (fn ['a 'b] '[a])))");
+    assert_eq!(output.stdout, "2\n");
+    let output = run_code("(defmacro 'm ['x] '[x]) (print (len m))");
+    assert_eq!(output.stdout, "1\n");
 }
 
 #[test]
@@ -241,6 +445,107 @@ fn test_loop() {
     assert_eq!(stdout, "0\n1\n2\n".to_string());
 }
 
+#[test]
+fn test_macro() {
+    let stdout = run_file("examples/macro.lcore".to_string());
+    let expect = "x is greater than 5\n\
+                  x is not less than 5\n\
+                  Hello\n\
+                  Hello\n\
+                  (quote [( if ( < 5 x ) (quote [( print x )]) )])\n\
+                  (quote [( print x )])\n\
+                  <Macro when>\n\
+                  2\n";
+    assert_eq!(stdout, expect.to_string());
+}
+
+#[test]
+fn test_malformed() {
+    // Mistakes in a program should be reported as errors, never as panics
+    let snippets = [
+        "(if 5 '[(print 1)])",
+        "(if True)",
+        "(if True 5)",
+        "(if False '[1] 5)",
+        "(+ 1)",
+        "(+ 1 2 3)",
+        "(- 1 \"a\")",
+        "(* \"ab\" -3)",
+        "(* \"ab\" 9223372036854775807)",
+        "(* [1] 99999999999999999)",
+        "(* \"\" 9223372036854775807)",
+        "(* [] 1000000000000)",
+        "(/ 1 0)",
+        "(/ -9223372036854775808 -1)",
+        "(+ 9223372036854775807 1)",
+        "(** 2 100)",
+        "(** 2 9999999999)",
+        "(get)",
+        "(get [1 2 3] 3)",
+        "(get [] 0)",
+        "(get [1 2] \"x\")",
+        "(get (dict 'a 1) \"missing\")",
+        "(get 5 1)",
+        "(get \"abc\" 10)",
+        "(loop 'i \"x\" '[(print i)])",
+        "(loop 'i 3)",
+        "(loop 5 3 '[1])",
+        "(loop 'i 3 5)",
+        "(set)",
+        "(set 5 1)",
+        "(set '5 1)",
+        "(defn 5 ['x] '[1])",
+        "(defn 'f 5 '[1])",
+        "(defn 'f [5] '[1])",
+        "(defn 'f ['x] 5)",
+        "(fn [] 5)",
+        "(len 5)",
+        "(= 1)",
+        "(< 1)",
+        "(not 5)",
+        "(sel)",
+        "(sel 1 1 5)",
+        "(ret 1 2)",
+        "(swap)",
+        "(swap 'x '[0] 1)",
+        "(set 'a [1 2]) (swap 'a '[] 1)",
+        "(set 'a [1 2]) (swap 'a '[0 0] 1)",
+        "(set 'a (dict 'b 1)) (swap 'a '[c] 1)",
+        "(swap 5 '[0] 1)",
+        "(dict 'a)",
+        "(import)",
+        "(import \"no/such/file.lcore\")",
+        "(type 'p 5)",
+        "(type 'p '[a a])",
+        "(new 5)",
+        "(raise)",
+        "(raise (new error 1 2))",
+        "(try 5 'e 5)",
+        "(try '[(raise 'X \"a\")] 5 '[1])",
+        "(defmacro 'm 5 5)",
+        "(defmacro 'm [] '[`[(m)]]) (m)",
+        "(defn 'r ['n] '[(set 'x (r n)) (ret x)]) (r 1)",
+        "(print 1 2)",
+        "(set 'x 5) (x 1)",
+        "(+ 99999999999999999999999 1)",
+        ")(",
+        "(print \"unterminated",
+    ];
+
+    for code in snippets.iter() {
+        let output = run_code(code);
+        let stderr = output.stderr;
+
+        assert_ne!(output.status, 101, "{} crashed", code);
+        assert!(
+            !stderr.contains("panicked"),
+            "{} panicked: {}",
+            code,
+            stderr
+        );
+    }
+}
+
 #[test]
 fn test_math() {
     let stdout = run_file("examples/math.lcore".to_string());
@@ -285,12 +590,43 @@ fn test_order() {
     assert_eq!(stdout, expect);
 }
 
-//#[test]
-#[allow(dead_code)]
-fn test_print() {
-    let stdout = run_file("examples/order.lcore".to_string());
+#[test]
+fn test_parse_error() {
+    let output = run_failing_file("examples/parse-error.lcore");
+    assert_eq!(output.status, 2);
+    assert!(output.stdout.is_empty());
+
+    let stderr = output.stderr;
+    let expect = "ParseError: unclosed `(` opened at line 6, column 1\n \
+                  --> examples/parse-error.lcore:6:1\n  \
+                  |\n\
+                  6 | (defn 'square ['x] '[\n  \
+                  | ^\n";
+    assert_eq!(stderr, expect.to_string());
+
+    let output = run_code("(print [1 2)");
+    let stderr = output.stderr;
+    assert_eq!(output.status, 2);
+    assert!(stderr.starts_with(
+        "ParseError: mismatched `)`: the `[` opened at line 1, column 8 is \
+         still open\n --> <string>:1:12\n"
+    ));
+
+    let output = run_code("(print \"abc)");
+    let stderr = output.stderr;
+    assert_eq!(output.status, 2);
+    assert!(stderr.starts_with("ParseError: unterminated string\n"));
+
+    let output = run_code("(print 1))");
+    let stderr = output.stderr;
+    assert_eq!(output.status, 2);
+    assert!(stderr
+        .starts_with("ParseError: unexpected `)` with nothing to close\n"));
+}
 
-    // TODO(pebaz): Use a regex for this:
+#[test]
+fn test_print() {
+    let stdout = run_file("examples/print.lcore".to_string());
     let expect = "String: Lambda Core version 0.1.0\n\
                   Boolean: True\n\
                   Integer: 11\n\
@@ -298,13 +634,30 @@ fn test_print() {
                   Null: Null\n\
                   Array: [1 2 [3 4]]\n\
                   __repr__ String: [\"This Should Be Quoted\"]\n\
-                  Function: <Func at 0x1e3958a5d78>\n\
+                  Function: <Func print>\n\
                   Return Value: 11\n\
                   hello\\nworld!\n"
         .to_string();
     assert_eq!(stdout, expect);
 }
 
+#[test]
+fn test_quasi_quote() {
+    let stdout = run_file("examples/quasi-quote.lcore".to_string());
+    let expect = "(quote [a 2 3 4 5])\n\
+                  (quote [1 2 0 0 [nested 2] end])\n\
+                  (quote [( print 10 ) ( print 100 )])\n\
+                  10\n\
+                  100\n\
+                  (quote [( + 10 2 )])\n\
+                  12\n\
+                  [(quote [2])]\n\
+                  8\n\
+                  (quote [( set (quote total) ( * total 3 ) )])\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+}
+
 #[test]
 fn test_quote() {
     let stdout = run_file("examples/quote.lcore".to_string());
@@ -333,6 +686,20 @@ fn test_stdlib() {
     assert_eq!(stdout, "256\n".to_string());
 }
 
+#[test]
+fn test_struct() {
+    let stdout = run_file("examples/struct.lcore".to_string());
+    let expect = "Pebaz\n\
+                  person { name: \"Pebaz\", age: 24 }\n\
+                  <Type person>\n\
+                  25\n\
+                  Wallace\n\
+                  True\n\
+                  False\n\
+                  2\n";
+    assert_eq!(stdout, expect.to_string());
+}
+
 #[test]
 fn test_swap() {
     let stdout = run_file("examples/swap.lcore".to_string());
@@ -353,3 +720,50 @@ fn test_swap() {
         After:  { \"inner2\": { \"inner3\": [\"SO MUCH INNER\" { \"like-so-much-inner\": \"VICTORY\" }] } }\n".to_string();
     assert_eq!(stdout, expect);
 }
+
+#[test]
+fn test_tail() {
+    let stdout = run_file("examples/tail.lcore".to_string());
+    let expect = "Done!\n\
+                  5000050000\n\
+                  False\n"
+        .to_string();
+    assert_eq!(stdout, expect);
+}
+
+#[test]
+fn test_traceback() {
+    let output = run_failing_file("examples/traceback.lcore");
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, "2\n");
+
+    let stderr = output.stderr;
+    let expect = "Traceback (most recent call last):\n  \
+                  File \"examples/traceback.lcore\", line 18, column 8, in <module>\n  \
+                  File \"examples/traceback.lcore\", line 13, column 18, in average\n  \
+                  File \"examples/traceback.lcore\", line 4, column 20, in divide\n\
+                  ZeroDivisionError: Division by zero\n";
+    assert_eq!(stderr, expect.to_string());
+}
+
+#[test]
+fn test_try() {
+    let stdout = run_file("examples/try.lcore".to_string());
+    let expect = "Before\n\
+                  ValueError\n\
+                  Something went wrong\n\
+                  error { kind: \"NameError\", message: \"Cannot lookup name: \"undefined-name\"\" }\n\
+                  Finally\n\
+                  Cleaning up\n\
+                  3\n\
+                  -10\n\
+                  Cannot divide by zero\n\
+                  10\n\
+                  Handling inner\n\
+                  Caught again: error { kind: \"NameError\", message: \"Inner\" }\n\
+                  Leaving early\n\
+                  Returned\n\
+                  ValueError\n\
+                  Not an error\n";
+    assert_eq!(stdout, expect.to_string());
+}
@@ -0,0 +1,271 @@
+use lambda_core::{Interpreter, LCoreError, NativeFunc, Symbol, Value};
+use std::cell::{Cell, RefCell};
+use std::io::Cursor;
+use std::rc::Rc;
+
+#[test]
+fn test_eval_str() {
+    let mut interpreter = Interpreter::new();
+
+    let result = interpreter.eval_str("(set 'x 40) (+ x 2)").unwrap();
+    assert_eq!(result, Value::Int(42));
+
+    // Globals are kept between calls
+    let result = interpreter.eval_str("(* x 2)").unwrap();
+    assert_eq!(result, Value::Int(80));
+}
+
+#[test]
+fn test_eval_file() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_file("examples/calc.lcore").unwrap();
+
+    let result =
+        interpreter.call_function("add", vec![Value::Int(2), Value::Int(8)]);
+    assert_eq!(result.unwrap(), Value::Int(10));
+
+    match interpreter.eval_file("examples/missing.lcore") {
+        Err(LCoreError::IOError(_)) => (),
+        other => panic!("Expected an IOError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_globals() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.get_global("x"), None);
+
+    interpreter.set_global("x", Value::String(Rc::new("Hello".to_string())));
+    let result = interpreter.eval_str("(+ x \" World\")").unwrap();
+    assert_eq!(result, Value::String(Rc::new("Hello World".to_string())));
+
+    interpreter.eval_str("(set 'x [1 2])").unwrap();
+    let expect = Value::Array(Rc::new(vec![Value::Int(1), Value::Int(2)]));
+    assert_eq!(interpreter.get_global("x"), Some(expect));
+
+    // Looking up a name that was never defined does not keep it around
+    assert_eq!(interpreter.get_global("never-defined"), None);
+    assert!(interpreter
+        .call_function("never-called", Vec::new())
+        .is_err());
+    assert_eq!(Symbol::lookup("never-defined"), None);
+    assert_eq!(Symbol::lookup("never-called"), None);
+}
+
+#[test]
+fn test_call_function() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("(defn 'square ['x] '[(ret (* x x))])")
+        .unwrap();
+
+    let result = interpreter.call_function("square", vec![Value::Int(7)]);
+    assert_eq!(result.unwrap(), Value::Int(49));
+
+    let result = interpreter.call_function("len", vec![Value::Int(7)]);
+    assert_eq!(result.unwrap_err().kind(), "TypeError");
+
+    let result = interpreter.call_function("square", vec![]);
+    assert_eq!(result.unwrap_err().kind(), "ArityError");
+
+    let result = interpreter.call_function("missing", vec![]);
+    assert_eq!(result.unwrap_err().kind(), "NameError");
+}
+
+#[test]
+fn test_enclosing_scopes() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "(defn 'counter [] '[
+                (set 'count 0)
+                (loop 'i 3 '[(set 'count (+ count i))])
+                (ret (fn ['step] '[(set 'count (+ count step)) (ret count)]))
+            ])
+            (set 'next (counter))",
+        )
+        .unwrap();
+
+    // The closure keeps updating the variable of the call that made it
+    let result = interpreter.call_function("next", vec![Value::Int(10)]);
+    assert_eq!(result.unwrap(), Value::Int(13));
+    let result = interpreter.call_function("next", vec![Value::Int(10)]);
+    assert_eq!(result.unwrap(), Value::Int(23));
+    assert_eq!(interpreter.get_global("count"), None);
+}
+
+#[test]
+fn test_shared_values() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "(set 'a [1 (dict 'k [2])])
+            (set 'b a)
+            (defn 'change ['x] '[(swap 'x '[1 k 0] 5) (ret x)])
+            (set 'c (change a))
+            (swap 'b '[0] 3)",
+        )
+        .unwrap();
+
+    // Changing a copy never changes the value it was copied from
+    let mut get = |code: &str| interpreter.eval_str(code).unwrap();
+    let k = |n| Value::Array(Rc::new(vec![Value::Int(n)]));
+    assert_eq!(get("(get a 0)"), Value::Int(1));
+    assert_eq!(get("(get (get a 1) 'k)"), k(2));
+    assert_eq!(get("(get b 0)"), Value::Int(3));
+    assert_eq!(get("(get (get b 1) 'k)"), k(2));
+    assert_eq!(get("(get (get c 1) 'k)"), k(5));
+}
+
+#[test]
+fn test_dict_keys() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "(type 'point '[x y])
+            (set 'd (dict [1 \"a\"] \"array\" (new point 1 2) \"struct\"
+                          -0.0 \"zero\" 'name \"string\"))",
+        )
+        .unwrap();
+
+    let mut get = |code: &str| interpreter.eval_str(code).unwrap();
+    let string = |s: &str| Value::String(Rc::new(s.to_string()));
+    assert_eq!(get("(get d [1 \"a\"])"), string("array"));
+    assert_eq!(get("(get d (new point 1 2))"), string("struct"));
+    assert_eq!(get("(get d 0.0)"), string("zero"));
+    assert_eq!(get("(get d \"name\")"), string("string"));
+
+    for code in ["(dict (dict) 1)", "(dict [print] 1)"].iter() {
+        let err = interpreter.eval_str(code).unwrap_err();
+        assert_eq!(err.kind(), "TypeError");
+    }
+    let err = interpreter.eval_str("(dict (dict) 1)").unwrap_err();
+    assert_eq!(err.message(), "Cannot use Dict as a dict key");
+}
+
+#[test]
+fn test_errors() {
+    let mut interpreter = Interpreter::new();
+
+    let err = interpreter.eval_str("(/ 1 0)").unwrap_err();
+    assert_eq!(err.kind(), "ZeroDivisionError");
+
+    let err = interpreter.eval_str("(print (").unwrap_err();
+    assert_eq!(err.kind(), "ParseError");
+    assert_eq!(err.exit_status(), 2);
+
+    // Where the code could not be parsed is kept apart from the message
+    assert_eq!(err.message(), "unclosed `(` opened at line 1, column 8");
+    match err {
+        LCoreError::ParseError(_, Some(location)) => {
+            assert_eq!((location.line, location.column), (1, 8));
+            assert_eq!(location.text, "(print (");
+        }
+        other => panic!("Expected a ParseError, got {:?}", other),
+    }
+
+    let err = interpreter.eval_str("(ret 1)").unwrap_err();
+    assert_eq!(err.message(), "\"ret\" used outside of a function");
+
+    match interpreter.eval_str("(exit 3)") {
+        Err(LCoreError::ExitError(3)) => (),
+        other => panic!("Expected an ExitError, got {:?}", other),
+    }
+
+    // The interpreter can still be used after an error
+    let result = interpreter.eval_str("(+ 1 2)").unwrap();
+    assert_eq!(result, Value::Int(3));
+}
+
+#[test]
+fn test_register_function() {
+    let mut interpreter = Interpreter::new();
+
+    let count = Rc::new(Cell::new(0));
+    let counter = count.clone();
+    interpreter.register_function(
+        NativeFunc::new("count", move |args, _| {
+            let step = match args.as_array().first() {
+                Some(Value::Int(step)) => *step,
+                _ => 1,
+            };
+            counter.set(counter.get() + step);
+            Ok(Value::Int(counter.get()))
+        })
+        .with_arity(0, 1),
+    );
+
+    let result = interpreter.eval_str("(count) (count 10)").unwrap();
+    assert_eq!(result, Value::Int(11));
+    assert_eq!(count.get(), 11);
+
+    let result = interpreter.call_function("count", vec![]);
+    assert_eq!(result.unwrap(), Value::Int(12));
+
+    let err = interpreter.eval_str("(count 1 2)").unwrap_err();
+    assert_eq!(
+        err.message(),
+        "Wrong number of arguments on call to \"count\": 2/0-1"
+    );
+    assert_eq!(count.get(), 12);
+
+    match interpreter.get_global("count") {
+        Some(Value::Func(func)) => assert_eq!(func.name(), "count"),
+        other => panic!("Expected a Func, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_streams() {
+    let mut interpreter = Interpreter::new();
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(output.clone());
+    interpreter.set_input(Rc::new(RefCell::new(Cursor::new("World\r\n"))));
+
+    interpreter
+        .eval_str(
+            "(set 'name (input \"Name: \")) \
+             (print (+ \"Hello \" name)) \
+             (prin (input))",
+        )
+        .unwrap();
+
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    assert_eq!(output, "Name: Hello World\nNull");
+}
+
+#[test]
+fn test_deep_recursion() {
+    // Runs on a thread with the default stack size rather than a large one
+    let thread = std::thread::spawn(|| {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str(
+                "(defn 'depth ['n] '[
+                    (if (= n 0) '[(ret 0)])
+                    (ret (+ 1 (depth (- n 1))))
+                ])
+                (defn 'guarded ['n] '[
+                    (try '[(ret (+ 1 (guarded (- n 1))))] 'e '[(ret 0)])
+                ])",
+            )
+            .unwrap();
+
+        let result =
+            interpreter.call_function("depth", vec![Value::Int(9_000)]);
+        assert_eq!(result.unwrap(), Value::Int(9_000));
+
+        let err = interpreter
+            .call_function("depth", vec![Value::Int(20_000)])
+            .unwrap_err();
+        assert_eq!(err.message(), "Maximum recursion depth of 10000 exceeded");
+
+        // Running out of depth can be caught like any other error
+        let result =
+            interpreter.call_function("guarded", vec![Value::Int(-1)]);
+        assert!(matches!(result.unwrap(), Value::Int(_)));
+    });
+
+    thread.join().unwrap();
+}